@@ -0,0 +1,125 @@
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields};
+
+/// Generates a `{Name}Cells` struct with each field of `{Name}` wrapped in its own
+/// `MaybeAtomicRefCell`, plus `borrow_{field}` and `borrow_{field}_mut` accessors.
+///
+/// Only structs with named fields are supported.
+#[proc_macro_derive(MaybeAtomicFields)]
+pub fn derive_maybe_atomic_fields(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    Span::call_site(),
+                    "MaybeAtomicFields requires a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                Span::call_site(),
+                "MaybeAtomicFields can only be derived for structs",
+            ))
+        }
+    };
+
+    let vis = &input.vis;
+    let name = &input.ident;
+    let cells = format_ident!("{}Cells", name);
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let generics = &input.generics;
+
+    let idents: Vec<_> = fields.iter().map(|f| f.ident.as_ref().unwrap()).collect();
+    let cell_fields = fields.iter().map(|f| {
+        let vis = &f.vis;
+        let ident = &f.ident;
+        let ty = &f.ty;
+        quote! { #vis #ident: ::maybe_atomic_refcell::MaybeAtomicRefCell<#ty> }
+    });
+    let accessors = fields.iter().map(|f| {
+        let vis = &f.vis;
+        let ident = f.ident.as_ref().unwrap();
+        let ty = &f.ty;
+        let borrow = format_ident!("borrow_{}", ident);
+        let borrow_mut = format_ident!("borrow_{}_mut", ident);
+        let borrow_doc = format!("Immutably borrows `{}`. See `MaybeAtomicRefCell::borrow`.", ident);
+        let borrow_mut_doc = format!(
+            "Mutably borrows `{}`. See `MaybeAtomicRefCell::borrow_mut`.",
+            ident
+        );
+        quote! {
+            #[doc = #borrow_doc]
+            ///
+            /// # Safety
+            ///
+            /// The field must not be mutably borrowed for the lifetime of the returned guard.
+            #[inline]
+            #[track_caller]
+            #vis unsafe fn #borrow(&self) -> ::maybe_atomic_refcell::MaybeAtomicRef<'_, #ty> {
+                // SAFETY: Upheld by the caller.
+                unsafe { self.#ident.borrow() }
+            }
+
+            #[doc = #borrow_mut_doc]
+            ///
+            /// # Safety
+            ///
+            /// The field must not be borrowed at all for the lifetime of the returned guard.
+            #[inline]
+            #[track_caller]
+            #vis unsafe fn #borrow_mut(&self) -> ::maybe_atomic_refcell::MaybeAtomicRefMut<'_, #ty> {
+                // SAFETY: Upheld by the caller.
+                unsafe { self.#ident.borrow_mut() }
+            }
+        }
+    });
+    let cells_doc = format!(
+        "`{}` with each field wrapped in its own `MaybeAtomicRefCell`.",
+        name
+    );
+
+    Ok(quote! {
+        #[doc = #cells_doc]
+        #vis struct #cells #generics #where_clause {
+            #(#cell_fields,)*
+        }
+
+        impl #impl_generics #cells #ty_generics #where_clause {
+            /// Wraps each field in its own `MaybeAtomicRefCell`.
+            #[inline]
+            #vis fn new(value: #name #ty_generics) -> Self {
+                Self {
+                    #(#idents: ::maybe_atomic_refcell::MaybeAtomicRefCell::new(value.#idents),)*
+                }
+            }
+
+            /// Consumes the cells, returning the wrapped fields.
+            #[inline]
+            #vis fn into_inner(self) -> #name #ty_generics {
+                #name {
+                    #(#idents: self.#idents.into_inner(),)*
+                }
+            }
+
+            #(#accessors)*
+        }
+
+        impl #impl_generics ::core::convert::From<#name #ty_generics> for #cells #ty_generics #where_clause {
+            #[inline]
+            fn from(value: #name #ty_generics) -> Self {
+                Self::new(value)
+            }
+        }
+    })
+}
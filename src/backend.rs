@@ -0,0 +1,505 @@
+//! The runtime checks behind checked cells: `atomic_refcell` by default, or a blocking
+//! reader-writer lock built from `std::sync` with the `blocking` feature (or without the
+//! `atomic_refcell` feature).
+//!
+//! The lock is a `Mutex`-protected borrow count and a `Condvar`, rather than a `RwLock`, because
+//! `RwLock` guards can't be sent between threads, and the cell's guards can.
+//!
+//! With the `side_table` feature, either kind of check keeps its state in a global table instead
+//! of in each cell.
+
+#[cfg(feature = "side_table")]
+mod side_table;
+
+#[cfg(feature = "side_table")]
+pub(crate) use side_table::{clone_shared, BorrowCell, Exclusive, Shared};
+
+#[cfg(all(
+    any(feature = "blocking", not(feature = "atomic_refcell")),
+    not(feature = "side_table")
+))]
+use crate::reentrancy;
+use crate::{generation, invariant};
+#[cfg(any(not(feature = "side_table"), feature = "change_detection"))]
+use std::alloc::Layout;
+#[cfg(all(
+    any(feature = "blocking", not(feature = "atomic_refcell")),
+    not(feature = "side_table")
+))]
+use std::cell::UnsafeCell;
+#[cfg(not(feature = "side_table"))]
+use std::mem::ManuallyDrop;
+#[cfg(all(
+    any(feature = "blocking", not(feature = "atomic_refcell")),
+    not(feature = "side_table")
+))]
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+
+/// Tracks the borrows of a value, like an `AtomicRefCell`.
+#[cfg(all(
+    feature = "atomic_refcell",
+    not(any(feature = "blocking", feature = "side_table"))
+))]
+pub(crate) struct BorrowCell<T: ?Sized> {
+    inner: atomic_refcell::AtomicRefCell<T>,
+}
+
+/// Tracks the borrows of a value, like an `AtomicRefCell`.
+#[cfg(all(
+    any(feature = "blocking", not(feature = "atomic_refcell")),
+    not(feature = "side_table")
+))]
+pub(crate) struct BorrowCell<T: ?Sized> {
+    borrows: Borrows,
+    value: UnsafeCell<T>,
+}
+
+/// The number of immutable borrows, or `EXCLUSIVE`.
+#[cfg(all(
+    any(feature = "blocking", not(feature = "atomic_refcell")),
+    not(feature = "side_table")
+))]
+pub(crate) struct Borrows {
+    count: Mutex<usize>,
+    released: Condvar,
+}
+
+#[cfg(all(
+    any(feature = "blocking", not(feature = "atomic_refcell")),
+    not(feature = "side_table")
+))]
+const EXCLUSIVE: usize = usize::MAX;
+
+/// An immutable borrow of a `BorrowCell`, released on drop.
+#[cfg(all(
+    feature = "atomic_refcell",
+    not(any(feature = "blocking", feature = "side_table"))
+))]
+pub(crate) type Shared<'b> = atomic_refcell::AtomicRef<'b, ()>;
+/// An immutable borrow of a `BorrowCell`, released on drop.
+#[cfg(all(
+    any(feature = "blocking", not(feature = "atomic_refcell")),
+    not(feature = "side_table")
+))]
+pub(crate) struct Shared<'b>(&'b Borrows);
+
+/// A mutable borrow of a `BorrowCell`, released on drop.
+#[cfg(all(
+    feature = "atomic_refcell",
+    not(any(feature = "blocking", feature = "side_table"))
+))]
+pub(crate) type Exclusive<'b> = atomic_refcell::AtomicRefMut<'b, ()>;
+/// A mutable borrow of a `BorrowCell`, released on drop.
+#[cfg(all(
+    any(feature = "blocking", not(feature = "atomic_refcell")),
+    not(feature = "side_table")
+))]
+pub(crate) struct Exclusive<'b>(&'b Borrows);
+
+/// Takes another immutable borrow alongside `shared`.
+#[cfg(all(
+    feature = "atomic_refcell",
+    not(any(feature = "blocking", feature = "side_table"))
+))]
+#[inline]
+pub(crate) fn clone_shared<'b>(shared: &Shared<'b>) -> Shared<'b> {
+    atomic_refcell::AtomicRef::clone(shared)
+}
+
+/// Takes another immutable borrow alongside `shared`.
+#[cfg(all(
+    any(feature = "blocking", not(feature = "atomic_refcell")),
+    not(feature = "side_table")
+))]
+#[inline]
+pub(crate) fn clone_shared<'b>(shared: &Shared<'b>) -> Shared<'b> {
+    *shared.0.lock() += 1;
+    Shared(shared.0)
+}
+
+/// Removes what is kept about the value behind `ptr` outside of its cell, as the cell is being
+/// dropped or consumed, so that it neither leaks nor carries over to a later cell at the same
+/// address.
+#[inline]
+fn forget<T: ?Sized>(ptr: *const T) {
+    generation::remove(ptr);
+    invariant::clear(ptr);
+}
+
+/// Moves the value at `value`, within the allocation of `boxed`, to a `Box` of its own, and
+/// frees `boxed` without dropping anything in it.
+///
+/// # Safety
+///
+/// `value` must point into `boxed`, and anything else in `boxed` must not need dropping.
+#[cfg(any(not(feature = "side_table"), feature = "change_detection"))]
+pub(crate) unsafe fn extract<B: ?Sized, T: ?Sized>(boxed: *mut B, value: *mut T) -> Box<T> {
+    // SAFETY: See above.
+    unsafe {
+        let layout = Layout::for_value(&*value);
+        let allocation = allocate(layout);
+        std::ptr::copy_nonoverlapping(value as *const u8, allocation, layout.size());
+        free(boxed);
+        Box::from_raw(with_address(value, allocation))
+    }
+}
+
+/// Allocates memory for `layout`, like a `Box`.
+#[cfg(any(not(feature = "side_table"), feature = "change_detection"))]
+fn allocate(layout: Layout) -> *mut u8 {
+    if layout.size() == 0 {
+        return std::ptr::without_provenance_mut(layout.align());
+    }
+    // SAFETY: The layout isn't zero-sized.
+    let allocation = unsafe { std::alloc::alloc(layout) };
+    if allocation.is_null() {
+        std::alloc::handle_alloc_error(layout);
+    }
+    allocation
+}
+
+/// Frees the allocation of a `Box`, without dropping its contents.
+///
+/// # Safety
+///
+/// `boxed` must come from `Box::into_raw`, and not be used afterwards.
+#[cfg(any(not(feature = "side_table"), feature = "change_detection"))]
+unsafe fn free<T: ?Sized>(boxed: *mut T) {
+    // SAFETY: See above.
+    unsafe {
+        let layout = Layout::for_value(&*boxed);
+        if layout.size() != 0 {
+            std::alloc::dealloc(boxed as *mut u8, layout);
+        }
+    }
+}
+
+/// Returns `ptr` with its address replaced by `address`, keeping its metadata.
+#[cfg(any(not(feature = "side_table"), feature = "change_detection"))]
+fn with_address<T: ?Sized>(mut ptr: *mut T, address: *mut u8) -> *mut T {
+    // SAFETY: The address is the first field of any pointer, wide or not (as
+    // `<*mut T>::set_ptr_value` also assumes).
+    unsafe { *(&mut ptr as *mut *mut T as *mut *mut u8) = address };
+    ptr
+}
+
+#[cfg(all(
+    feature = "atomic_refcell",
+    not(any(feature = "blocking", feature = "side_table"))
+))]
+impl<T> BorrowCell<T> {
+    #[inline]
+    pub(crate) const fn new(value: T) -> BorrowCell<T> {
+        BorrowCell {
+            inner: atomic_refcell::AtomicRefCell::new(value),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn into_inner(self) -> T {
+        self.into_atomic_ref_cell().into_inner()
+    }
+
+    #[inline]
+    pub(crate) fn from_atomic_ref_cell(inner: atomic_refcell::AtomicRefCell<T>) -> BorrowCell<T> {
+        BorrowCell { inner }
+    }
+
+    #[inline]
+    pub(crate) fn into_atomic_ref_cell(self) -> atomic_refcell::AtomicRefCell<T> {
+        let cell = ManuallyDrop::new(self);
+        forget(cell.as_ptr());
+        // SAFETY: The cell isn't dropped, so the field is only moved out once.
+        unsafe { std::ptr::read(&cell.inner) }
+    }
+}
+
+#[cfg(all(
+    feature = "atomic_refcell",
+    not(any(feature = "blocking", feature = "side_table"))
+))]
+impl<T: ?Sized> Drop for BorrowCell<T> {
+    #[inline]
+    fn drop(&mut self) {
+        forget(self.as_ptr());
+    }
+}
+
+#[cfg(all(
+    feature = "atomic_refcell",
+    not(any(feature = "blocking", feature = "side_table"))
+))]
+impl<T: ?Sized> BorrowCell<T> {
+    #[inline]
+    pub(crate) fn as_ptr(&self) -> *mut T {
+        self.inner.as_ptr()
+    }
+
+    #[inline]
+    pub(crate) fn into_inner_boxed(self: Box<Self>) -> Box<T> {
+        let cell = Box::into_raw(self);
+        // SAFETY: The cell is the whole allocation.
+        unsafe { BorrowCell::extract_from(cell, cell) }
+    }
+
+    /// Moves the value out of `cell`, within the allocation of `boxed`, to a `Box` of its own,
+    /// dropping the rest of the cell and freeing `boxed`.
+    ///
+    /// # Safety
+    ///
+    /// As in `extract`, and `cell` must not be used afterwards.
+    #[inline]
+    pub(crate) unsafe fn extract_from<B: ?Sized>(cell: *mut Self, boxed: *mut B) -> Box<T> {
+        // SAFETY: The value is in the cell, and the borrow count doesn't need dropping.
+        unsafe {
+            forget((*cell).as_ptr());
+            extract(boxed, (*cell).as_ptr())
+        }
+    }
+
+    #[inline]
+    pub(crate) fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+
+    #[cfg(any(debug_assertions, feature = "safe"))]
+    #[inline]
+    pub(crate) fn as_atomic_ref_cell(&self) -> &atomic_refcell::AtomicRefCell<T> {
+        &self.inner
+    }
+
+    /// Immutably borrows the value, if it isn't mutably borrowed.
+    #[inline]
+    pub(crate) fn try_borrow(&self) -> Option<Shared<'_>> {
+        let borrow = self.inner.try_borrow().ok()?;
+        Some(atomic_refcell::AtomicRef::map(borrow, |_| &()))
+    }
+
+    /// Returns whether the value is borrowed at all.
+    #[inline]
+    pub(crate) fn is_borrowed(&self) -> bool {
+        self.inner.try_borrow_mut().is_err()
+    }
+
+    /// Immutably borrows the value, or returns `None` on a conflicting borrow.
+    #[inline]
+    pub(crate) fn borrow(&self) -> Option<Shared<'_>> {
+        self.try_borrow()
+    }
+
+    /// Mutably borrows the value, if it isn't borrowed at all.
+    #[inline]
+    pub(crate) fn try_borrow_mut(&self) -> Option<Exclusive<'_>> {
+        self.borrow_mut()
+    }
+
+    /// Mutably borrows the value, or returns `None` on a conflicting borrow.
+    #[inline]
+    pub(crate) fn borrow_mut(&self) -> Option<Exclusive<'_>> {
+        let borrow = self.inner.try_borrow_mut().ok()?;
+        Some(atomic_refcell::AtomicRefMut::map(borrow, |_| {
+            // A `Box` of a zero-sized type doesn't allocate, so nothing is leaked.
+            Box::leak(Box::new(()))
+        }))
+    }
+}
+
+#[cfg(all(
+    any(feature = "blocking", not(feature = "atomic_refcell")),
+    not(feature = "side_table")
+))]
+impl<T> BorrowCell<T> {
+    #[inline]
+    pub(crate) const fn new(value: T) -> BorrowCell<T> {
+        BorrowCell {
+            borrows: Borrows::new(),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn into_inner(self) -> T {
+        let cell = ManuallyDrop::new(self);
+        forget(cell.as_ptr());
+        // SAFETY: The cell isn't dropped, so the fields are only moved out once.
+        unsafe {
+            let _borrows = std::ptr::read(&cell.borrows);
+            std::ptr::read(&cell.value).into_inner()
+        }
+    }
+
+    #[cfg(feature = "atomic_refcell")]
+    #[inline]
+    pub(crate) fn from_atomic_ref_cell(inner: atomic_refcell::AtomicRefCell<T>) -> BorrowCell<T> {
+        BorrowCell::new(inner.into_inner())
+    }
+
+    #[cfg(feature = "atomic_refcell")]
+    #[inline]
+    pub(crate) fn into_atomic_ref_cell(self) -> atomic_refcell::AtomicRefCell<T> {
+        atomic_refcell::AtomicRefCell::new(self.into_inner())
+    }
+}
+
+#[cfg(all(
+    any(feature = "blocking", not(feature = "atomic_refcell")),
+    not(feature = "side_table")
+))]
+impl<T: ?Sized> BorrowCell<T> {
+    #[inline]
+    pub(crate) fn as_ptr(&self) -> *mut T {
+        self.value.get()
+    }
+
+    #[inline]
+    pub(crate) fn into_inner_boxed(self: Box<Self>) -> Box<T> {
+        let cell = Box::into_raw(self);
+        // SAFETY: The cell is the whole allocation.
+        unsafe { BorrowCell::extract_from(cell, cell) }
+    }
+
+    /// Moves the value out of `cell`, within the allocation of `boxed`, to a `Box` of its own,
+    /// dropping the rest of the cell and freeing `boxed`.
+    ///
+    /// # Safety
+    ///
+    /// As in `extract`, and `cell` must not be used afterwards.
+    #[inline]
+    pub(crate) unsafe fn extract_from<B: ?Sized>(cell: *mut Self, boxed: *mut B) -> Box<T> {
+        // SAFETY: The value is in the cell, and the borrow count is moved out, to be dropped.
+        unsafe {
+            forget((*cell).as_ptr());
+            let _borrows = std::ptr::read(std::ptr::addr_of!((*cell).borrows));
+            extract(boxed, (*cell).as_ptr())
+        }
+    }
+
+    #[inline]
+    pub(crate) fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    /// Immutably borrows the value, if it isn't mutably borrowed.
+    #[inline]
+    pub(crate) fn try_borrow(&self) -> Option<Shared<'_>> {
+        let mut count = self.borrows.lock();
+        (*count != EXCLUSIVE).then(|| {
+            *count += 1;
+            Shared(&self.borrows)
+        })
+    }
+
+    /// Returns whether the value is borrowed at all.
+    #[inline]
+    pub(crate) fn is_borrowed(&self) -> bool {
+        *self.borrows.lock() != 0
+    }
+
+    /// Mutably borrows the value, if it isn't borrowed at all.
+    #[inline]
+    pub(crate) fn try_borrow_mut(&self) -> Option<Exclusive<'_>> {
+        let mut count = self.borrows.lock();
+        (*count == 0).then(|| {
+            *count = EXCLUSIVE;
+            Exclusive(&self.borrows)
+        })
+    }
+
+    /// Immutably borrows the value, waiting for other threads to release conflicting borrows.
+    /// Returns `None` if this thread holds the conflicting borrow, which would deadlock.
+    #[inline]
+    pub(crate) fn borrow(&self) -> Option<Shared<'_>> {
+        let mut count = self.borrows.lock();
+        while *count == EXCLUSIVE {
+            if reentrancy::holds(reentrancy::id(self)) {
+                return None;
+            }
+            count = self.borrows.wait(count);
+        }
+        *count += 1;
+        Some(Shared(&self.borrows))
+    }
+
+    /// Mutably borrows the value, waiting for other threads to release conflicting borrows.
+    /// Returns `None` if this thread holds a conflicting borrow, which would deadlock.
+    #[inline]
+    pub(crate) fn borrow_mut(&self) -> Option<Exclusive<'_>> {
+        let mut count = self.borrows.lock();
+        while *count != 0 {
+            if reentrancy::holds(reentrancy::id(self)) {
+                return None;
+            }
+            count = self.borrows.wait(count);
+        }
+        *count = EXCLUSIVE;
+        Some(Exclusive(&self.borrows))
+    }
+}
+
+#[cfg(all(
+    any(feature = "blocking", not(feature = "atomic_refcell")),
+    not(feature = "side_table")
+))]
+impl<T: ?Sized> Drop for BorrowCell<T> {
+    #[inline]
+    fn drop(&mut self) {
+        forget(self.as_ptr());
+    }
+}
+
+#[cfg(all(
+    any(feature = "blocking", not(feature = "atomic_refcell")),
+    not(feature = "side_table")
+))]
+impl Borrows {
+    #[inline]
+    const fn new() -> Borrows {
+        Borrows {
+            count: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    // The mutex is never held while running user code, so poisoning is impossible in practice.
+    #[inline]
+    fn lock(&self) -> MutexGuard<'_, usize> {
+        self.count.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Waits for a borrow to be released, in a profiler scope with the `profiling` feature.
+    #[inline]
+    fn wait<'a>(&self, count: MutexGuard<'a, usize>) -> MutexGuard<'a, usize> {
+        #[cfg(feature = "profiling")]
+        profiling::scope!("waiting for borrow");
+        self.released
+            .wait(count)
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(all(
+    any(feature = "blocking", not(feature = "atomic_refcell")),
+    not(feature = "side_table")
+))]
+impl Drop for Shared<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        let mut count = self.0.lock();
+        *count -= 1;
+        if *count == 0 {
+            self.0.released.notify_all();
+        }
+    }
+}
+
+#[cfg(all(
+    any(feature = "blocking", not(feature = "atomic_refcell")),
+    not(feature = "side_table")
+))]
+impl Drop for Exclusive<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        *self.0.lock() = 0;
+        self.0.released.notify_all();
+    }
+}
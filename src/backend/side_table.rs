@@ -0,0 +1,285 @@
+//! Borrow state kept in a global table keyed by address, rather than in each cell, so that
+//! checked cells have the same layout as unchecked ones. Enabled by the `side_table` feature.
+//!
+//! Like the other backends, conflicting borrows from other threads wait with the `blocking`
+//! feature (or without the `atomic_refcell` feature), and fail otherwise.
+
+use super::forget;
+use crate::reentrancy;
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+
+/// Tracks the borrows of a value in the side table.
+#[repr(transparent)]
+pub(crate) struct BorrowCell<T: ?Sized> {
+    value: UnsafeCell<T>,
+}
+
+/// Identifies a cell in the table the same way as in the borrows this thread holds, so that a
+/// cell at the start of one this thread has borrowed waits for other threads, rather than being
+/// taken for a reentrant borrow.
+type Key = reentrancy::Id;
+
+/// The borrowed cells whose keys hash to the same shard, with their number of immutable borrows
+/// or `EXCLUSIVE`. Cells that aren't borrowed have no entry.
+struct Shard {
+    borrows: Mutex<Vec<(Key, usize)>>,
+    released: Condvar,
+}
+
+const EXCLUSIVE: usize = usize::MAX;
+
+const SHARD_BITS: u32 = 6;
+const SHARDS: usize = 1 << SHARD_BITS;
+
+static TABLE: [Shard; SHARDS] = [const {
+    Shard {
+        borrows: Mutex::new(Vec::new()),
+        released: Condvar::new(),
+    }
+}; SHARDS];
+
+/// Whether to wait for other threads to release conflicting borrows.
+const WAIT: bool = cfg!(any(feature = "blocking", not(feature = "atomic_refcell")));
+
+/// An immutable borrow of a `BorrowCell`, released on drop.
+pub(crate) struct Shared<'b> {
+    key: Key,
+    marker: PhantomData<&'b ()>,
+}
+
+/// A mutable borrow of a `BorrowCell`, released on drop.
+pub(crate) struct Exclusive<'b> {
+    key: Key,
+    marker: PhantomData<&'b ()>,
+}
+
+/// Takes another immutable borrow alongside `shared`.
+#[inline]
+pub(crate) fn clone_shared<'b>(shared: &Shared<'b>) -> Shared<'b> {
+    let acquired = acquire(shared.key, false, |count| Some(count + 1));
+    debug_assert!(acquired);
+    Shared {
+        key: shared.key,
+        marker: PhantomData,
+    }
+}
+
+impl<T> BorrowCell<T> {
+    #[inline]
+    pub(crate) const fn new(value: T) -> BorrowCell<T> {
+        BorrowCell {
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn into_inner(self) -> T {
+        let cell = ManuallyDrop::new(self);
+        forget(cell.as_ptr());
+        // SAFETY: The cell isn't dropped, so the value is only moved out once.
+        unsafe { std::ptr::read(&cell.value) }.into_inner()
+    }
+
+    #[cfg(feature = "atomic_refcell")]
+    #[inline]
+    pub(crate) fn from_atomic_ref_cell(inner: atomic_refcell::AtomicRefCell<T>) -> BorrowCell<T> {
+        BorrowCell::new(inner.into_inner())
+    }
+
+    #[cfg(feature = "atomic_refcell")]
+    #[inline]
+    pub(crate) fn into_atomic_ref_cell(self) -> atomic_refcell::AtomicRefCell<T> {
+        atomic_refcell::AtomicRefCell::new(self.into_inner())
+    }
+}
+
+impl<T: ?Sized> BorrowCell<T> {
+    #[inline]
+    pub(crate) fn as_ptr(&self) -> *mut T {
+        self.value.get()
+    }
+
+    #[inline]
+    pub(crate) fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    #[inline]
+    pub(crate) fn into_inner_boxed(self: Box<Self>) -> Box<T> {
+        forget(self.as_ptr());
+        // SAFETY: `BorrowCell<T>` is `repr(transparent)` over `UnsafeCell<T>`, which has the same
+        // memory layout as `T`.
+        unsafe { Box::from_raw(Box::into_raw(self) as *mut T) }
+    }
+
+    /// Moves the value out of `cell`, within the allocation of `boxed`, to a `Box` of its own,
+    /// freeing `boxed`.
+    ///
+    /// # Safety
+    ///
+    /// As in `extract`, and `cell` must not be used afterwards.
+    #[cfg(all(feature = "change_detection", any(debug_assertions, feature = "safe")))]
+    #[inline]
+    pub(crate) unsafe fn extract_from<B: ?Sized>(cell: *mut Self, boxed: *mut B) -> Box<T> {
+        // SAFETY: The value is in the cell, which holds nothing else.
+        unsafe {
+            forget((*cell).as_ptr());
+            super::extract(boxed, (*cell).as_ptr())
+        }
+    }
+
+    #[inline]
+    fn key(&self) -> Key {
+        reentrancy::id(self)
+    }
+
+    /// Immutably borrows the value, if it isn't mutably borrowed.
+    #[inline]
+    pub(crate) fn try_borrow(&self) -> Option<Shared<'_>> {
+        self.shared(false)
+    }
+
+    /// Returns whether the value is borrowed at all.
+    #[inline]
+    pub(crate) fn is_borrowed(&self) -> bool {
+        let key = self.key();
+        count(&shard(key).lock(), key) != 0
+    }
+
+    /// Immutably borrows the value, or returns `None` on a conflicting borrow (by this thread,
+    /// if waiting for other threads).
+    #[inline]
+    pub(crate) fn borrow(&self) -> Option<Shared<'_>> {
+        self.shared(WAIT)
+    }
+
+    /// Mutably borrows the value, if it isn't borrowed at all.
+    #[inline]
+    pub(crate) fn try_borrow_mut(&self) -> Option<Exclusive<'_>> {
+        self.exclusive(false)
+    }
+
+    /// Mutably borrows the value, or returns `None` on a conflicting borrow (by this thread,
+    /// if waiting for other threads).
+    #[inline]
+    pub(crate) fn borrow_mut(&self) -> Option<Exclusive<'_>> {
+        self.exclusive(WAIT)
+    }
+
+    #[inline]
+    fn exclusive(&self, wait: bool) -> Option<Exclusive<'_>> {
+        let key = self.key();
+        acquire(key, wait, |count| (count == 0).then_some(EXCLUSIVE)).then(|| Exclusive {
+            key,
+            marker: PhantomData,
+        })
+    }
+
+    #[inline]
+    fn shared(&self, wait: bool) -> Option<Shared<'_>> {
+        let key = self.key();
+        acquire(key, wait, |count| (count != EXCLUSIVE).then(|| count + 1)).then(|| Shared {
+            key,
+            marker: PhantomData,
+        })
+    }
+}
+
+impl<T: ?Sized> Drop for BorrowCell<T> {
+    #[inline]
+    fn drop(&mut self) {
+        forget(self.as_ptr());
+    }
+}
+
+impl Shard {
+    // The mutex is never held while running user code, so poisoning is impossible in practice.
+    #[inline]
+    fn lock(&self) -> MutexGuard<'_, Vec<(Key, usize)>> {
+        self.borrows.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Waits for a borrow to be released, in a profiler scope with the `profiling` feature.
+    #[inline]
+    fn wait<'a>(
+        &self,
+        borrows: MutexGuard<'a, Vec<(Key, usize)>>,
+    ) -> MutexGuard<'a, Vec<(Key, usize)>> {
+        #[cfg(feature = "profiling")]
+        profiling::scope!("waiting for borrow");
+        self.released
+            .wait(borrows)
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[inline]
+fn shard(key: Key) -> &'static Shard {
+    // Fibonacci hashing, so that neighboring cells land in different shards.
+    let hash = key.address.wrapping_mul(0x9E37_79B9_7F4A_7C15_u64 as usize);
+    &TABLE[hash >> (usize::BITS - SHARD_BITS)]
+}
+
+#[inline]
+fn count(borrows: &[(Key, usize)], key: Key) -> usize {
+    borrows
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map_or(0, |&(_, count)| count)
+}
+
+#[inline]
+fn set_count(borrows: &mut Vec<(Key, usize)>, key: Key, count: usize) {
+    match borrows.iter().position(|(k, _)| *k == key) {
+        Some(i) if count == 0 => {
+            borrows.swap_remove(i);
+        }
+        Some(i) => borrows[i].1 = count,
+        None if count == 0 => {}
+        None => borrows.push((key, count)),
+    }
+}
+
+/// Replaces the borrow count of `key` with the result of `f`, waiting for other threads to
+/// release conflicting borrows (for which `f` returns `None`) if `wait`. Returns `false` on a
+/// conflicting borrow it didn't wait for.
+#[inline]
+fn acquire(key: Key, wait: bool, f: impl Fn(usize) -> Option<usize>) -> bool {
+    let shard = shard(key);
+    let mut borrows = shard.lock();
+    loop {
+        if let Some(count) = f(count(&borrows, key)) {
+            set_count(&mut borrows, key, count);
+            return true;
+        }
+        if !wait || reentrancy::holds(key) {
+            return false;
+        }
+        borrows = shard.wait(borrows);
+    }
+}
+
+impl Drop for Shared<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        let shard = shard(self.key);
+        let mut borrows = shard.lock();
+        let count = count(&borrows, self.key) - 1;
+        set_count(&mut borrows, self.key, count);
+        if count == 0 {
+            shard.released.notify_all();
+        }
+    }
+}
+
+impl Drop for Exclusive<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        let shard = shard(self.key);
+        set_count(&mut shard.lock(), self.key, 0);
+        shard.released.notify_all();
+    }
+}
@@ -0,0 +1,367 @@
+//! Buffers built out of several `MaybeAtomicRefCell`s, flipped between at frame boundaries.
+
+use crate::{MaybeAtomicRef, MaybeAtomicRefCell, MaybeAtomicRefMut};
+use std::fmt;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A front buffer that is read while a back buffer is written, until `swap` flips them.
+///
+/// Each buffer is a `MaybeAtomicRefCell<T>`, so access is checked exactly when
+/// `MaybeAtomicRefCell` is checked.
+///
+/// ```
+/// use maybe_atomic_refcell::MaybeDoubleBuffer;
+///
+/// let mut positions = MaybeDoubleBuffer::new(vec![0, 1], vec![0, 1]);
+/// for _ in 0..2 {
+///     unsafe {
+///         let read = positions.read();
+///         let mut write = positions.write();
+///         for (w, r) in write.iter_mut().zip(read.iter()) {
+///             *w = r + 1;
+///         }
+///     }
+///     positions.swap();
+/// }
+/// assert_eq!(unsafe { &*positions.read() }, &[2, 3]);
+/// ```
+pub struct MaybeDoubleBuffer<T> {
+    buffers: [MaybeAtomicRefCell<T>; 2],
+    /// Index of the front buffer.
+    front: usize,
+}
+
+impl<T> MaybeDoubleBuffer<T> {
+    /// Creates a new `MaybeDoubleBuffer` with the given `front` and `back` buffers.
+    #[inline]
+    pub const fn new(front: T, back: T) -> MaybeDoubleBuffer<T> {
+        MaybeDoubleBuffer {
+            buffers: [
+                MaybeAtomicRefCell::new(front),
+                MaybeAtomicRefCell::new(back),
+            ],
+            front: 0,
+        }
+    }
+
+    /// Consumes the `MaybeDoubleBuffer`, returning the front and back buffers.
+    #[inline]
+    pub fn into_inner(self) -> (T, T) {
+        let [a, b] = self.buffers.map(MaybeAtomicRefCell::into_inner);
+        if self.front == 0 {
+            (a, b)
+        } else {
+            (b, a)
+        }
+    }
+
+    /// Immutably borrows the front buffer. Performs runtime checks in debug mode, but not in
+    /// release mode (hence `unsafe`).
+    ///
+    /// # Safety
+    ///
+    /// The front buffer must not be mutably borrowed for the lifetime of the returned guard.
+    #[inline]
+    #[track_caller]
+    pub unsafe fn read(&self) -> MaybeAtomicRef<'_, T> {
+        unsafe { self.buffers[self.front].borrow() }
+    }
+
+    /// Mutably borrows the back buffer. Performs runtime checks in debug mode, but not in
+    /// release mode (hence `unsafe`).
+    ///
+    /// # Safety
+    ///
+    /// The back buffer must not be borrowed at all for the lifetime of the returned guard.
+    #[inline]
+    #[track_caller]
+    pub unsafe fn write(&self) -> MaybeAtomicRefMut<'_, T> {
+        unsafe { self.buffers[self.front ^ 1].borrow_mut() }
+    }
+
+    /// Flips the buffers, so that the back buffer is read and the front buffer is written.
+    ///
+    /// No runtime checks take place because this call borrows `MaybeDoubleBuffer` mutably at
+    /// compile-time.
+    #[inline]
+    pub fn swap(&mut self) {
+        self.front ^= 1;
+    }
+
+    /// Returns mutable references to the front and back buffers.
+    ///
+    /// No runtime checks take place (unless debug assertions are enabled)
+    /// because this call borrows `MaybeDoubleBuffer` mutably at compile-time.
+    #[inline]
+    pub fn get_mut(&mut self) -> (&mut T, &mut T) {
+        let [a, b] = &mut self.buffers;
+        if self.front == 0 {
+            (a.get_mut(), b.get_mut())
+        } else {
+            (b.get_mut(), a.get_mut())
+        }
+    }
+}
+
+impl<T: Clone> MaybeDoubleBuffer<T> {
+    /// Creates a new `MaybeDoubleBuffer` with both buffers containing `value`.
+    #[inline]
+    pub fn new_cloned(value: T) -> MaybeDoubleBuffer<T> {
+        MaybeDoubleBuffer::new(value.clone(), value)
+    }
+}
+
+impl<T: Default> Default for MaybeDoubleBuffer<T> {
+    #[inline]
+    fn default() -> MaybeDoubleBuffer<T> {
+        MaybeDoubleBuffer::new(T::default(), T::default())
+    }
+}
+
+impl<T: Debug> Debug for MaybeDoubleBuffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MaybeDoubleBuffer")
+            .field("front", &self.buffers[self.front])
+            .field("back", &self.buffers[self.front ^ 1])
+            .finish()
+    }
+}
+
+/// A buffer that a producer `write`s and `publish`es snapshots of, and a consumer `read`s the
+/// latest of, without either ever waiting for the other.
+///
+/// Each buffer is a `MaybeAtomicRefCell<T>`, so access is checked exactly when
+/// `MaybeAtomicRefCell` is checked. Handing buffers between the producer and the consumer takes
+/// a single atomic swap in every build; the rest relies on there being only one of each.
+///
+/// ```
+/// use maybe_atomic_refcell::MaybeTripleBuffer;
+///
+/// let gain = MaybeTripleBuffer::new_cloned(0.5);
+/// std::thread::scope(|s| {
+///     s.spawn(|| unsafe {
+///         *gain.write() = 0.75;
+///         gain.publish();
+///     });
+/// });
+/// assert_eq!(unsafe { *gain.read() }, 0.75);
+/// ```
+pub struct MaybeTripleBuffer<T> {
+    buffers: [MaybeAtomicRefCell<T>; 3],
+    /// Index of the buffer written by the producer. Only accessed by the producer.
+    input: AtomicUsize,
+    /// Index of the buffer between the producer and the consumer, plus `FRESH` if it was
+    /// published since the consumer last took it.
+    back: AtomicUsize,
+    /// Index of the buffer read by the consumer. Only accessed by the consumer.
+    output: AtomicUsize,
+}
+
+/// Set in `MaybeTripleBuffer::back` when it holds a snapshot the consumer hasn't seen.
+const FRESH: usize = 0b100;
+const INDEX: usize = 0b011;
+
+impl<T> MaybeTripleBuffer<T> {
+    /// Creates a new `MaybeTripleBuffer` with all three buffers containing the given values,
+    /// the first of which is read until something is published.
+    #[inline]
+    const fn new(output: T, back: T, input: T) -> MaybeTripleBuffer<T> {
+        MaybeTripleBuffer {
+            buffers: [
+                MaybeAtomicRefCell::new(output),
+                MaybeAtomicRefCell::new(back),
+                MaybeAtomicRefCell::new(input),
+            ],
+            input: AtomicUsize::new(2),
+            back: AtomicUsize::new(1),
+            output: AtomicUsize::new(0),
+        }
+    }
+
+    /// Consumes the `MaybeTripleBuffer`, returning the latest published value.
+    #[inline]
+    pub fn into_inner(mut self) -> T {
+        self.refresh();
+        let output = *self.output.get_mut();
+        let [a, b, c] = self.buffers.map(MaybeAtomicRefCell::into_inner);
+        match output {
+            0 => a,
+            1 => b,
+            _ => c,
+        }
+    }
+
+    /// Mutably borrows the producer's buffer, which holds whatever the producer last wrote to
+    /// it (not necessarily the latest published value). Performs runtime checks in debug mode,
+    /// but not in release mode (hence `unsafe`).
+    ///
+    /// # Safety
+    ///
+    /// Only one thread at a time may act as the producer (calling `write` or `publish`), and
+    /// the returned guard must be dropped before the next `publish`.
+    #[inline]
+    #[track_caller]
+    pub unsafe fn write(&self) -> MaybeAtomicRefMut<'_, T> {
+        unsafe { self.buffers[self.input.load(Ordering::Relaxed)].borrow_mut() }
+    }
+
+    /// Publishes the producer's buffer to the consumer, taking an unused buffer in its place.
+    ///
+    /// # Safety
+    ///
+    /// See `write`.
+    #[inline]
+    pub unsafe fn publish(&self) {
+        let input = self.input.load(Ordering::Relaxed);
+        let back = self.back.swap(input | FRESH, Ordering::AcqRel);
+        self.input.store(back & INDEX, Ordering::Relaxed);
+    }
+
+    /// Returns whether something was published since the consumer last called `read`.
+    #[inline]
+    pub fn has_update(&self) -> bool {
+        self.back.load(Ordering::Relaxed) & FRESH != 0
+    }
+
+    /// Immutably borrows the latest published value (or the initial value, if nothing was
+    /// published yet). Performs runtime checks in debug mode, but not in release mode (hence
+    /// `unsafe`).
+    ///
+    /// # Safety
+    ///
+    /// Only one thread at a time may act as the consumer (calling `read`), and the returned
+    /// guard must be dropped before the next `read`.
+    #[inline]
+    #[track_caller]
+    pub unsafe fn read(&self) -> MaybeAtomicRef<'_, T> {
+        if self.has_update() {
+            let output = self.output.load(Ordering::Relaxed);
+            let back = self.back.swap(output, Ordering::AcqRel);
+            self.output.store(back & INDEX, Ordering::Relaxed);
+        }
+        unsafe { self.buffers[self.output.load(Ordering::Relaxed)].borrow() }
+    }
+
+    /// Takes the latest published value as the consumer's buffer, if it hasn't already.
+    #[inline]
+    fn refresh(&mut self) {
+        let back = self.back.get_mut();
+        if *back & FRESH != 0 {
+            let output = self.output.get_mut();
+            std::mem::swap(output, back);
+            *output &= INDEX;
+        }
+    }
+}
+
+impl<T: Clone> MaybeTripleBuffer<T> {
+    /// Creates a new `MaybeTripleBuffer` with all three buffers containing `value`.
+    #[inline]
+    pub fn new_cloned(value: T) -> MaybeTripleBuffer<T> {
+        MaybeTripleBuffer::new(value.clone(), value.clone(), value)
+    }
+}
+
+impl<T: Default> Default for MaybeTripleBuffer<T> {
+    #[inline]
+    fn default() -> MaybeTripleBuffer<T> {
+        MaybeTripleBuffer::new(T::default(), T::default(), T::default())
+    }
+}
+
+impl<T: Debug> Debug for MaybeTripleBuffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MaybeTripleBuffer")
+            .field("output", &self.buffers[self.output.load(Ordering::Relaxed)])
+            .field("has_update", &self.has_update())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{MaybeDoubleBuffer, MaybeTripleBuffer};
+
+    #[test]
+    fn it_works() {
+        let mut buffer = MaybeDoubleBuffer::new_cloned(1);
+        unsafe {
+            *buffer.write() = *buffer.read() + 1;
+            assert_eq!(*buffer.read(), 1);
+        }
+        buffer.swap();
+        unsafe {
+            assert_eq!(*buffer.read(), 2);
+            *buffer.write() = 3;
+        }
+
+        let (front, back) = buffer.get_mut();
+        *front += 10;
+        *back += 20;
+        assert_eq!(buffer.into_inner(), (12, 23));
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(any(debug_assertions, feature = "safe"), should_panic)]
+    fn it_panics_write_write() {
+        let buffer = MaybeDoubleBuffer::new(1, 2);
+        unsafe {
+            let _write1 = buffer.write();
+            let _write2 = buffer.write();
+        }
+    }
+
+    #[test]
+    fn triple() {
+        let buffer = MaybeTripleBuffer::new_cloned(0);
+        unsafe {
+            *buffer.write() = 1;
+            assert_eq!(*buffer.read(), 0);
+            buffer.publish();
+            assert!(buffer.has_update());
+            *buffer.write() = 2;
+            assert_eq!(*buffer.read(), 1);
+            assert!(!buffer.has_update());
+            assert_eq!(*buffer.read(), 1);
+            buffer.publish();
+        }
+        assert_eq!(buffer.into_inner(), 2);
+    }
+
+    #[test]
+    fn triple_threads() {
+        let buffer = MaybeTripleBuffer::new_cloned(0);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                for i in 1..=1000 {
+                    unsafe {
+                        *buffer.write() = i;
+                        buffer.publish();
+                    }
+                }
+            });
+            s.spawn(|| {
+                let mut last = 0;
+                while last < 1000 {
+                    let latest = unsafe { *buffer.read() };
+                    assert!(latest >= last);
+                    last = latest;
+                }
+            });
+        });
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(any(debug_assertions, feature = "safe"), should_panic)]
+    fn it_panics_triple_publish_write() {
+        let buffer = MaybeTripleBuffer::new_cloned(0);
+        unsafe {
+            let _write = buffer.write();
+            buffer.publish();
+            let _read = buffer.read();
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Injects small random delays around borrow acquisition and release, to widen race windows in
+//! tests. Enabled by the `chaos` feature.
+
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::thread;
+use std::time::Duration;
+
+thread_local! {
+    static STATE: Cell<u64> = Cell::new(RandomState::new().hash_one(thread::current().id()) | 1);
+}
+
+/// Does nothing, yields, spins, or sleeps briefly, at random.
+#[inline(never)]
+pub(crate) fn perturb() {
+    // xorshift64
+    let Ok(random) = STATE.try_with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        x
+    }) else {
+        return;
+    };
+    match random % 4 {
+        0 => {}
+        1 => thread::yield_now(),
+        2 => {
+            for _ in 0..(random >> 2) % 1024 {
+                std::hint::spin_loop();
+            }
+        }
+        _ => thread::sleep(Duration::from_micros((random >> 2) % 100)),
+    }
+}
+
+/// Calls `perturb` when dropped, just before a borrow is released.
+pub(crate) struct Release;
+
+impl Drop for Release {
+    #[inline]
+    fn drop(&mut self) {
+        perturb();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::MaybeAtomicRefCell;
+
+    #[test]
+    fn it_perturbs() {
+        let cells: Vec<_> = (0..4).map(MaybeAtomicRefCell::new).collect();
+        std::thread::scope(|scope| {
+            for cell in &cells {
+                scope.spawn(move || {
+                    for _ in 0..100 {
+                        *unsafe { cell.borrow_mut() } += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(
+            cells
+                .into_iter()
+                .map(MaybeAtomicRefCell::into_inner)
+                .collect::<Vec<_>>(),
+            [100, 101, 102, 103]
+        );
+    }
+}
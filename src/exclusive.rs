@@ -0,0 +1,130 @@
+//! A wrapper that is `Sync` for any `Send` value, by handing out mutable access only, like
+//! `SyncWrapper` from the `sync_wrapper` crate.
+
+use crate::{MaybeAtomicRefCell, MaybeAtomicRefMut};
+use std::fmt;
+
+/// A value that is only ever accessed mutably, so it can be shared between threads even if it
+/// isn't `Sync`, as long as it is `Send`.
+///
+/// `get_mut` is checked at compile time. The `unsafe` escape hatch, `get`, is checked at runtime
+/// in debug mode (or with the `safe` feature), like `MaybeAtomicRefCell::borrow_mut`.
+///
+/// ```
+/// use maybe_atomic_refcell::MaybeExclusive;
+/// use std::cell::Cell;
+///
+/// struct State {
+///     // Not `Sync`.
+///     count: Cell<u32>,
+/// }
+///
+/// fn assert_sync<T: Sync>(_: &T) {}
+///
+/// let mut state = MaybeExclusive::new(State { count: Cell::new(0) });
+/// assert_sync(&state);
+/// state.get_mut().count.set(1);
+/// assert_eq!(state.into_inner().count.get(), 1);
+/// ```
+#[repr(transparent)]
+pub struct MaybeExclusive<T: ?Sized> {
+    inner: MaybeAtomicRefCell<T>,
+}
+
+impl<T> MaybeExclusive<T> {
+    /// Creates a new `MaybeExclusive` containing `value`.
+    #[inline]
+    pub const fn new(value: T) -> MaybeExclusive<T> {
+        MaybeExclusive {
+            inner: MaybeAtomicRefCell::new(value),
+        }
+    }
+
+    /// Consumes the `MaybeExclusive`, returning the wrapped value.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+}
+
+impl<T: ?Sized> MaybeExclusive<T> {
+    /// Returns a mutable reference to the wrapped value.
+    ///
+    /// No runtime checks take place because this call borrows `MaybeExclusive` mutably at
+    /// compile-time.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+
+    /// Accesses the wrapped value through a shared reference. Since the value may not be `Sync`,
+    /// the access is always exclusive, even if only for reading. Performs runtime checks in debug
+    /// mode, but not in release mode (hence `unsafe`).
+    ///
+    /// # Safety
+    ///
+    /// The value must not be accessed in any other way for the lifetime of the returned guard.
+    #[inline]
+    #[track_caller]
+    pub unsafe fn get(&self) -> MaybeAtomicRefMut<'_, T> {
+        unsafe { self.inner.borrow_mut() }
+    }
+}
+
+unsafe impl<T: ?Sized + Send> Sync for MaybeExclusive<T> {}
+
+impl<T: Default> Default for MaybeExclusive<T> {
+    #[inline]
+    fn default() -> MaybeExclusive<T> {
+        MaybeExclusive::new(T::default())
+    }
+}
+
+impl<T> From<T> for MaybeExclusive<T> {
+    #[inline]
+    fn from(value: T) -> MaybeExclusive<T> {
+        MaybeExclusive::new(value)
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for MaybeExclusive<T> {
+    // Showing the value would take a shared reference to it.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MaybeExclusive").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::MaybeExclusive;
+    use std::cell::Cell;
+
+    #[test]
+    fn it_works() {
+        let exclusive = MaybeExclusive::new(Cell::new(0));
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                // One thread at a time.
+                s.spawn(|| {
+                    let guard = unsafe { exclusive.get() };
+                    guard.set(guard.get() + 1);
+                })
+                .join()
+                .unwrap();
+            }
+        });
+        assert_eq!(format!("{:?}", exclusive), "MaybeExclusive { .. }");
+        assert_eq!(exclusive.into_inner().get(), 4);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(any(debug_assertions, feature = "safe"), should_panic)]
+    fn it_panics_get_get() {
+        let exclusive = MaybeExclusive::new(Cell::new(0));
+        unsafe {
+            let _a = exclusive.get();
+            let _b = exclusive.get();
+        }
+    }
+}
@@ -0,0 +1,890 @@
+//! Cells that are always, or never, checked, regardless of the build profile. They share the
+//! guard types of `MaybeAtomicRefCell`, so they can be mixed field by field.
+
+use crate::backend::{self, BorrowCell};
+#[cfg(feature = "chaos")]
+use crate::chaos;
+#[cfg(any(debug_assertions, feature = "safe"))]
+use crate::flags::{self, BorrowFlag};
+use crate::hook::{self, WriteEvent};
+use crate::{generation, invariant, reentrancy, MaybeAtomicRef, MaybeAtomicRefMut};
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::panic::Location;
+use std::ptr::NonNull;
+use std::sync::Arc;
+
+/// The borrow held by a checked `MaybeAtomicRef`.
+pub struct CheckedBorrow<'b> {
+    #[cfg(feature = "chaos")]
+    _chaos: chaos::Release,
+    borrow: Shared<'b>,
+    held: reentrancy::Held,
+}
+
+/// The borrow held by a checked `MaybeAtomicRefMut`.
+pub struct CheckedBorrowMut<'b> {
+    #[cfg(feature = "chaos")]
+    _chaos: chaos::Release,
+    borrow: Exclusive<'b>,
+    held: reentrancy::Held,
+}
+
+/// An immutable borrow of a cell, or of a flag in a `MaybeAtomicArena`.
+enum Shared<'b> {
+    Cell(backend::Shared<'b>),
+    #[cfg(any(debug_assertions, feature = "safe"))]
+    Flag(flags::Shared<'b>),
+}
+
+/// A mutable borrow, possibly split between several guards.
+enum Exclusive<'b> {
+    Whole(Write<'b>),
+    Split(Arc<Write<'b>>),
+}
+
+/// A mutable borrow, reported to the `on_write` hook once released.
+struct Write<'b> {
+    lock: Option<Lock<'b>>,
+    event: WriteEvent,
+}
+
+/// A mutable borrow of a cell, or of a flag in a `MaybeAtomicArena`.
+// Only held to be released on drop.
+#[allow(dead_code)]
+enum Lock<'b> {
+    Cell(backend::Exclusive<'b>),
+    #[cfg(any(debug_assertions, feature = "safe"))]
+    Flag(flags::Exclusive<'b>),
+}
+
+/// The (absent) borrow held by an unchecked `MaybeAtomicRef` or `MaybeAtomicRefMut`.
+#[derive(Clone)]
+pub struct UncheckedBorrow(());
+
+/// A mutable borrow that can be split between several `MaybeAtomicRefMut`s, with
+/// `MaybeAtomicRefMut::map_split`.
+pub trait SplitBorrow: Sized + sealed::Sealed {
+    #[doc(hidden)]
+    fn split(self) -> (Self, Self);
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+impl<'b> CheckedBorrow<'b> {
+    #[inline]
+    #[track_caller]
+    fn new(cell: reentrancy::Id, borrow: Shared<'b>) -> CheckedBorrow<'b> {
+        CheckedBorrow {
+            #[cfg(feature = "chaos")]
+            _chaos: chaos::Release,
+            borrow,
+            held: reentrancy::Held::new(cell, false, Location::caller()),
+        }
+    }
+}
+
+impl Clone for CheckedBorrow<'_> {
+    #[inline]
+    fn clone(&self) -> Self {
+        CheckedBorrow {
+            #[cfg(feature = "chaos")]
+            _chaos: chaos::Release,
+            borrow: match &self.borrow {
+                Shared::Cell(borrow) => Shared::Cell(backend::clone_shared(borrow)),
+                #[cfg(any(debug_assertions, feature = "safe"))]
+                Shared::Flag(borrow) => Shared::Flag(borrow.clone()),
+            },
+            held: self.held.clone(),
+        }
+    }
+}
+
+impl<'b> CheckedBorrowMut<'b> {
+    #[inline]
+    fn new(cell: reentrancy::Id, borrow: Lock<'b>, event: WriteEvent) -> CheckedBorrowMut<'b> {
+        CheckedBorrowMut {
+            #[cfg(feature = "chaos")]
+            _chaos: chaos::Release,
+            held: reentrancy::Held::new(cell, true, event.location),
+            borrow: Exclusive::Whole(Write {
+                lock: Some(borrow),
+                event,
+            }),
+        }
+    }
+}
+
+impl sealed::Sealed for CheckedBorrowMut<'_> {}
+
+impl SplitBorrow for CheckedBorrowMut<'_> {
+    #[inline]
+    fn split(self) -> (Self, Self) {
+        let borrow = match self.borrow {
+            Exclusive::Whole(borrow) => Arc::new(borrow),
+            Exclusive::Split(borrow) => borrow,
+        };
+        let other = CheckedBorrowMut {
+            #[cfg(feature = "chaos")]
+            _chaos: chaos::Release,
+            borrow: Exclusive::Split(Arc::clone(&borrow)),
+            held: self.held.clone(),
+        };
+        let this = CheckedBorrowMut {
+            #[cfg(feature = "chaos")]
+            _chaos: self._chaos,
+            borrow: Exclusive::Split(borrow),
+            held: self.held,
+        };
+        (this, other)
+    }
+}
+
+impl Drop for Write<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        // Released first, so that the hook can borrow the value. Already taken if the borrow
+        // was converted into an `AtomicRefMut`, which isn't reported.
+        if let Some(lock) = self.lock.take() {
+            // Checked while still borrowed, so that no other borrow sees a broken invariant. If
+            // the check panics, `lock` is still released.
+            if !std::thread::panicking() {
+                invariant::check(&self.event);
+            }
+            generation::bump(&self.event);
+            drop(lock);
+            hook::on_write(&self.event);
+        }
+    }
+}
+
+/// Describes a mutable borrow of the value behind `ptr`, made by the caller, for the `on_write`
+/// hook.
+#[inline]
+#[track_caller]
+fn write_event<T: ?Sized>(ptr: *mut T) -> WriteEvent {
+    WriteEvent {
+        type_name: std::any::type_name::<T>(),
+        address: reentrancy::address(ptr),
+        location: Location::caller(),
+    }
+}
+
+impl sealed::Sealed for UncheckedBorrow {}
+
+impl SplitBorrow for UncheckedBorrow {
+    #[inline]
+    fn split(self) -> (Self, Self) {
+        (UncheckedBorrow(()), UncheckedBorrow(()))
+    }
+}
+
+/// Reports a borrow violation by panicking or, with the `abort` feature, by printing it (and a
+/// backtrace, if enabled by `RUST_BACKTRACE`) and aborting, since unwinding might not be allowed.
+#[cold]
+#[inline(never)]
+#[track_caller]
+pub(crate) fn violation(message: fmt::Arguments) -> ! {
+    #[cfg(feature = "abort")]
+    {
+        eprintln!(
+            "{} at {}{}",
+            message,
+            std::panic::Location::caller(),
+            reentrancy::History
+        );
+        let backtrace = std::backtrace::Backtrace::capture();
+        if backtrace.status() == std::backtrace::BacktraceStatus::Captured {
+            eprintln!("{}", backtrace);
+        }
+        std::process::abort();
+    }
+    #[cfg(not(feature = "abort"))]
+    panic!("{}{}", message, reentrancy::History)
+}
+
+#[cfg(all(
+    feature = "atomic_refcell",
+    not(any(feature = "blocking", feature = "side_table"))
+))]
+impl<'b, T: ?Sized> MaybeAtomicRef<'b, T, CheckedBorrow<'b>> {
+    /// Converts a checked `MaybeAtomicRef` into the `AtomicRef` backing it, or returns it as is
+    /// if it isn't backed by one (e.g. if it comes from a `MaybeAtomicArena`).
+    ///
+    /// Only available if the runtime checks are backed by `AtomicRefCell` (not with the
+    /// `blocking` or `side_table` features).
+    #[inline]
+    pub fn into_atomic_ref(
+        orig: MaybeAtomicRef<'b, T, CheckedBorrow<'b>>,
+    ) -> Result<atomic_refcell::AtomicRef<'b, T>, MaybeAtomicRef<'b, T, CheckedBorrow<'b>>> {
+        match orig.borrow.borrow {
+            Shared::Cell(borrow) => {
+                let value = orig.value;
+                // SAFETY: The borrow keeps the value immutably borrowed for `'b`.
+                Ok(atomic_refcell::AtomicRef::map(borrow, |_| unsafe {
+                    value.as_ref()
+                }))
+            }
+            #[cfg(any(debug_assertions, feature = "safe"))]
+            borrow => Err(MaybeAtomicRef {
+                value: orig.value,
+                borrow: CheckedBorrow {
+                    borrow,
+                    ..orig.borrow
+                },
+                marker: PhantomData,
+            }),
+        }
+    }
+}
+
+#[cfg(all(
+    feature = "atomic_refcell",
+    not(any(feature = "blocking", feature = "side_table"))
+))]
+impl<'b, T: ?Sized> MaybeAtomicRefMut<'b, T, CheckedBorrowMut<'b>> {
+    /// Converts a checked `MaybeAtomicRefMut` into the `AtomicRefMut` backing it, or returns it
+    /// as is if it isn't backed by one (e.g. if it comes from a `MaybeAtomicArena`, or was split
+    /// with `map_split`).
+    ///
+    /// Only available if the runtime checks are backed by `AtomicRefCell` (not with the
+    /// `blocking` or `side_table` features). The `on_write` hook isn't called for the returned
+    /// borrow.
+    #[inline]
+    pub fn into_atomic_ref_mut(
+        orig: MaybeAtomicRefMut<'b, T, CheckedBorrowMut<'b>>,
+    ) -> Result<atomic_refcell::AtomicRefMut<'b, T>, MaybeAtomicRefMut<'b, T, CheckedBorrowMut<'b>>>
+    {
+        let mut orig = orig;
+        match &mut orig.borrow.borrow {
+            Exclusive::Whole(Write {
+                lock: lock @ Some(Lock::Cell(_)),
+                ..
+            }) => {
+                // Taken without releasing it, which also skips the `on_write` hook.
+                let Some(Lock::Cell(borrow)) = lock.take() else {
+                    unreachable!()
+                };
+                let mut value = orig.value;
+                // SAFETY: The borrow keeps the value mutably borrowed for `'b`.
+                Ok(atomic_refcell::AtomicRefMut::map(borrow, |_| unsafe {
+                    value.as_mut()
+                }))
+            }
+            _ => Err(orig),
+        }
+    }
+}
+
+/// Immutably borrows `cell`, returning `None` if it is mutably borrowed.
+#[inline]
+pub(crate) fn try_borrow<T: ?Sized>(
+    cell: &BorrowCell<T>,
+) -> Option<MaybeAtomicRef<'_, T, CheckedBorrow<'_>>> {
+    cell.try_borrow().map(|borrow| guard(cell, borrow))
+}
+
+/// Immutably borrows `cell`, panicking if it is mutably borrowed (by this thread, with the
+/// `blocking` backend, which waits for other threads instead).
+#[inline]
+#[track_caller]
+pub(crate) fn borrow<T: ?Sized>(cell: &BorrowCell<T>) -> MaybeAtomicRef<'_, T, CheckedBorrow<'_>> {
+    #[cfg(feature = "chaos")]
+    chaos::perturb();
+    let borrow = cell.borrow();
+    #[cfg(feature = "chaos")]
+    chaos::perturb();
+    match borrow {
+        Some(borrow) => guard(cell, borrow),
+        None => violation(format_args!(
+            "already mutably borrowed {}",
+            reentrancy::culprit(reentrancy::id(cell))
+        )),
+    }
+}
+
+/// Mutably borrows `cell`, returning `None` if it is borrowed at all (by this thread, with the
+/// `blocking` backend, which waits for other threads instead).
+#[inline]
+#[track_caller]
+pub(crate) fn acquire_mut<T: ?Sized>(
+    cell: &BorrowCell<T>,
+) -> Option<MaybeAtomicRefMut<'_, T, CheckedBorrowMut<'_>>> {
+    let event = write_event(cell.as_ptr());
+    #[cfg(feature = "chaos")]
+    chaos::perturb();
+    let borrow = cell.borrow_mut();
+    #[cfg(feature = "chaos")]
+    chaos::perturb();
+    borrow.map(|borrow| guard_mut(cell, borrow, event))
+}
+
+/// Mutably borrows `cell` on behalf of the caller at `location`, returning `None` if it is
+/// borrowed at all, without waiting for other threads (even with the `blocking` backend).
+#[inline]
+pub(crate) fn try_borrow_mut<'b, T: ?Sized>(
+    cell: &'b BorrowCell<T>,
+    location: &'static Location<'static>,
+) -> Option<MaybeAtomicRefMut<'b, T, CheckedBorrowMut<'b>>> {
+    let event = WriteEvent {
+        location,
+        ..write_event(cell.as_ptr())
+    };
+    cell.try_borrow_mut()
+        .map(|borrow| guard_mut(cell, borrow, event))
+}
+
+/// Mutably borrows `cell`, panicking if it is borrowed at all. See `acquire_mut`.
+#[inline]
+#[track_caller]
+pub(crate) fn borrow_mut<T: ?Sized>(
+    cell: &BorrowCell<T>,
+) -> MaybeAtomicRefMut<'_, T, CheckedBorrowMut<'_>> {
+    acquire_mut(cell).unwrap_or_else(|| {
+        violation(format_args!(
+            "already borrowed {}",
+            reentrancy::culprit(reentrancy::id(cell))
+        ))
+    })
+}
+
+/// Panics if `cell` is borrowed at all, without waiting for the borrow to be released.
+#[inline]
+#[track_caller]
+pub(crate) fn assert_not_borrowed<T: ?Sized>(cell: &BorrowCell<T>) {
+    if cell.is_borrowed() {
+        violation(format_args!(
+            "still borrowed {}",
+            if reentrancy::holds(reentrancy::id(cell)) {
+                "by this thread"
+            } else {
+                "by another thread"
+            }
+        ));
+    }
+}
+
+/// Panics if `cell` is mutably borrowed, without waiting for the borrow to be released.
+#[inline]
+#[track_caller]
+pub(crate) fn assert_not_mutably_borrowed<T: ?Sized>(cell: &BorrowCell<T>) {
+    if cell.try_borrow().is_none() {
+        violation(format_args!(
+            "still mutably borrowed {}",
+            if reentrancy::holds(reentrancy::id(cell)) {
+                "by this thread"
+            } else {
+                "by another thread"
+            }
+        ));
+    }
+}
+
+#[inline]
+#[track_caller]
+fn guard<'b, T: ?Sized>(
+    cell: &'b BorrowCell<T>,
+    borrow: backend::Shared<'b>,
+) -> MaybeAtomicRef<'b, T, CheckedBorrow<'b>> {
+    MaybeAtomicRef {
+        // Derived from the cell, not the borrow, so that the borrow doesn't need to carry `T`.
+        value: unsafe { NonNull::new_unchecked(cell.as_ptr()) },
+        borrow: CheckedBorrow::new(reentrancy::id(cell), Shared::Cell(borrow)),
+        marker: PhantomData,
+    }
+}
+
+#[inline]
+fn guard_mut<'b, T: ?Sized>(
+    cell: &'b BorrowCell<T>,
+    borrow: backend::Exclusive<'b>,
+    event: WriteEvent,
+) -> MaybeAtomicRefMut<'b, T, CheckedBorrowMut<'b>> {
+    MaybeAtomicRefMut {
+        value: unsafe { NonNull::new_unchecked(cell.as_ptr()) },
+        borrow: CheckedBorrowMut::new(reentrancy::id(cell), Lock::Cell(borrow), event),
+        marker: PhantomData,
+    }
+}
+
+/// Immutably borrows the value behind `ptr`, tracked by `flag`, panicking if it is mutably
+/// borrowed.
+///
+/// # Safety
+///
+/// `ptr` must be valid for reads and writes for `'b`, and only accessed under `flag`.
+#[cfg(any(debug_assertions, feature = "safe"))]
+#[inline]
+#[track_caller]
+pub(crate) unsafe fn borrow_flagged<'b, T: ?Sized>(
+    flag: &'b BorrowFlag,
+    ptr: *mut T,
+) -> MaybeAtomicRef<'b, T, CheckedBorrow<'b>> {
+    #[cfg(feature = "chaos")]
+    chaos::perturb();
+    let borrow = flag.try_borrow();
+    #[cfg(feature = "chaos")]
+    chaos::perturb();
+    match borrow {
+        Some(borrow) => MaybeAtomicRef {
+            value: unsafe { NonNull::new_unchecked(ptr) },
+            borrow: CheckedBorrow::new(reentrancy::id(flag), Shared::Flag(borrow)),
+            marker: PhantomData,
+        },
+        None => violation(format_args!(
+            "already mutably borrowed {}",
+            reentrancy::culprit(reentrancy::id(flag))
+        )),
+    }
+}
+
+/// Mutably borrows the value behind `ptr`, tracked by `flag`, panicking if it is borrowed at
+/// all.
+///
+/// # Safety
+///
+/// See `borrow_flagged`.
+#[cfg(any(debug_assertions, feature = "safe"))]
+#[inline]
+#[track_caller]
+pub(crate) unsafe fn borrow_mut_flagged<'b, T: ?Sized>(
+    flag: &'b BorrowFlag,
+    ptr: *mut T,
+) -> MaybeAtomicRefMut<'b, T, CheckedBorrowMut<'b>> {
+    #[cfg(feature = "chaos")]
+    chaos::perturb();
+    let borrow = flag.try_borrow_mut();
+    #[cfg(feature = "chaos")]
+    chaos::perturb();
+    match borrow {
+        Some(borrow) => MaybeAtomicRefMut {
+            value: unsafe { NonNull::new_unchecked(ptr) },
+            borrow: CheckedBorrowMut::new(
+                reentrancy::id(flag),
+                Lock::Flag(borrow),
+                write_event(ptr),
+            ),
+            marker: PhantomData,
+        },
+        None => violation(format_args!(
+            "already borrowed {}",
+            reentrancy::culprit(reentrancy::id(flag))
+        )),
+    }
+}
+
+/// Immutably borrows the value behind `ptr`, without any checks.
+///
+/// # Safety
+///
+/// `ptr` must be valid for reads, and not mutated, for `'b`.
+#[inline]
+pub(crate) unsafe fn borrow_unchecked<'b, T: ?Sized>(
+    ptr: *mut T,
+) -> MaybeAtomicRef<'b, T, UncheckedBorrow> {
+    MaybeAtomicRef {
+        value: unsafe { NonNull::new_unchecked(ptr) },
+        borrow: UncheckedBorrow(()),
+        marker: PhantomData,
+    }
+}
+
+/// Mutably borrows the value behind `ptr`, without any checks.
+///
+/// # Safety
+///
+/// `ptr` must be valid for reads and writes, and not otherwise accessed, for `'b`.
+#[inline]
+pub(crate) unsafe fn borrow_mut_unchecked<'b, T: ?Sized>(
+    ptr: *mut T,
+) -> MaybeAtomicRefMut<'b, T, UncheckedBorrow> {
+    MaybeAtomicRefMut {
+        value: unsafe { NonNull::new_unchecked(ptr) },
+        borrow: UncheckedBorrow(()),
+        marker: PhantomData,
+    }
+}
+
+/// Like a `MaybeAtomicRefCell` but with runtime checks in every build profile.
+#[repr(transparent)]
+pub struct CheckedAtomicRefCell<T: ?Sized> {
+    inner: BorrowCell<T>,
+}
+
+impl<T> CheckedAtomicRefCell<T> {
+    /// Creates a new `CheckedAtomicRefCell` containing `value`.
+    #[inline]
+    pub const fn new(value: T) -> CheckedAtomicRefCell<T> {
+        CheckedAtomicRefCell {
+            inner: BorrowCell::new(value),
+        }
+    }
+
+    /// Consumes the `CheckedAtomicRefCell`, returning the wrapped value.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+
+    /// Boxes a `CheckedAtomicRefCell` containing a boxed value, in a new allocation. Like
+    /// `MaybeAtomicRefCell::from_boxed`.
+    #[inline]
+    #[allow(clippy::boxed_local)]
+    pub fn from_boxed(value: Box<T>) -> Box<CheckedAtomicRefCell<T>> {
+        Box::new(CheckedAtomicRefCell::new(*value))
+    }
+
+    /// Consumes the `CheckedAtomicRefCell`, returning an `AtomicRefCell` containing the wrapped
+    /// value.
+    #[cfg(feature = "atomic_refcell")]
+    #[inline]
+    pub fn into_atomic_ref_cell(self) -> atomic_refcell::AtomicRefCell<T> {
+        self.inner.into_atomic_ref_cell()
+    }
+
+    /// Sets an invariant of the value, checked whenever a mutable borrow of it is released. Like
+    /// `MaybeAtomicRefCell::set_invariant`, but always checked.
+    ///
+    /// # Safety
+    ///
+    /// See `MaybeAtomicRefCell::set_invariant`.
+    #[inline]
+    pub unsafe fn set_invariant(&self, invariant: impl Fn(&T) + Send + Sync + 'static)
+    where
+        T: 'static,
+    {
+        unsafe { invariant::set(self.as_ptr(), invariant) }
+    }
+
+    /// Removes the invariant set by `set_invariant`, if any.
+    #[inline]
+    pub fn clear_invariant(&self) {
+        invariant::clear(self.as_ptr());
+    }
+}
+
+impl<T: ?Sized> CheckedAtomicRefCell<T> {
+    /// Consumes a boxed `CheckedAtomicRefCell`, returning the wrapped value, boxed. Like
+    /// `MaybeAtomicRefCell::into_inner_boxed`.
+    #[inline]
+    pub fn into_inner_boxed(self: Box<Self>) -> Box<T> {
+        // SAFETY: `CheckedAtomicRefCell<T>` is `repr(transparent)` over `BorrowCell<T>`.
+        unsafe { Box::from_raw(Box::into_raw(self) as *mut BorrowCell<T>) }.into_inner_boxed()
+    }
+
+    /// Immutably borrows the wrapped value. Always performs runtime checks, so unlike
+    /// `MaybeAtomicRefCell::borrow` this is safe.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently mutably borrowed.
+    #[inline]
+    #[track_caller]
+    pub fn borrow(&self) -> MaybeAtomicRef<'_, T, CheckedBorrow<'_>> {
+        borrow(&self.inner)
+    }
+
+    /// Mutably borrows the wrapped value. Always performs runtime checks, so unlike
+    /// `MaybeAtomicRefCell::borrow_mut` this is safe.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed.
+    #[inline]
+    #[track_caller]
+    pub fn borrow_mut(&self) -> MaybeAtomicRefMut<'_, T, CheckedBorrowMut<'_>> {
+        borrow_mut(&self.inner)
+    }
+
+    /// Immutably borrows the wrapped value, returning `None` if it is currently mutably borrowed
+    /// (without waiting, even with the `blocking` backend).
+    #[inline]
+    pub fn try_borrow(&self) -> Option<MaybeAtomicRef<'_, T, CheckedBorrow<'_>>> {
+        try_borrow(&self.inner)
+    }
+
+    /// Mutably borrows the wrapped value, returning `None` if it is currently borrowed (without
+    /// waiting, even with the `blocking` backend).
+    #[inline]
+    #[track_caller]
+    pub fn try_borrow_mut(&self) -> Option<MaybeAtomicRefMut<'_, T, CheckedBorrowMut<'_>>> {
+        try_borrow_mut(&self.inner, Location::caller())
+    }
+
+    /// Panics if the value is borrowed at all. Like
+    /// `MaybeAtomicRefCell::debug_assert_not_borrowed`, but always checked.
+    #[inline]
+    #[track_caller]
+    pub fn assert_not_borrowed(&self) {
+        assert_not_borrowed(&self.inner);
+    }
+
+    /// Panics if the value is mutably borrowed. Like
+    /// `MaybeAtomicRefCell::debug_assert_not_mutably_borrowed`, but always checked.
+    #[inline]
+    #[track_caller]
+    pub fn assert_not_mutably_borrowed(&self) {
+        assert_not_mutably_borrowed(&self.inner);
+    }
+
+    /// Returns a raw pointer to the underlying data in this cell.
+    ///
+    /// External synchronization is needed to avoid data races when dereferencing
+    /// the pointer.
+    #[inline]
+    pub fn as_ptr(&self) -> *mut T {
+        self.inner.as_ptr()
+    }
+
+    /// Returns a raw pointer to the underlying data in this cell, along with the generation of
+    /// the cell, for `validate`. Like `MaybeAtomicRefCell::as_versioned_ptr`, but always counted.
+    #[inline]
+    pub fn as_versioned_ptr(&self) -> (*mut T, u64) {
+        (self.as_ptr(), generation::current(self.as_ptr()))
+    }
+
+    /// Panics if `ptr` and `generation`, returned by `as_versioned_ptr`, are stale. Like
+    /// `MaybeAtomicRefCell::debug_validate`, but always checked.
+    #[inline]
+    #[track_caller]
+    pub fn validate(&self, ptr: *const T, generation: u64) {
+        generation::validate(self.as_ptr(), ptr, generation);
+    }
+
+    /// Returns a mutable reference to the wrapped value.
+    ///
+    /// No runtime checks take place because this call borrows `CheckedAtomicRefCell` mutably
+    /// at compile-time.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+}
+
+unsafe impl<T: ?Sized + Send> Send for CheckedAtomicRefCell<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for CheckedAtomicRefCell<T> {}
+
+impl<T: Default> Default for CheckedAtomicRefCell<T> {
+    #[inline]
+    fn default() -> CheckedAtomicRefCell<T> {
+        CheckedAtomicRefCell::new(T::default())
+    }
+}
+
+impl<T> From<T> for CheckedAtomicRefCell<T> {
+    fn from(t: T) -> CheckedAtomicRefCell<T> {
+        CheckedAtomicRefCell::new(t)
+    }
+}
+
+#[cfg(feature = "atomic_refcell")]
+impl<T> From<atomic_refcell::AtomicRefCell<T>> for CheckedAtomicRefCell<T> {
+    fn from(cell: atomic_refcell::AtomicRefCell<T>) -> CheckedAtomicRefCell<T> {
+        CheckedAtomicRefCell {
+            inner: BorrowCell::from_atomic_ref_cell(cell),
+        }
+    }
+}
+
+impl<T: ?Sized + Debug> Debug for CheckedAtomicRefCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut debug = f.debug_struct("CheckedAtomicRefCell");
+        match try_borrow(&self.inner) {
+            Some(borrow) => debug.field("value", &&*borrow),
+            None => debug.field("value", &format_args!("<mutably borrowed>")),
+        };
+        debug.finish()
+    }
+}
+
+/// Like a `MaybeAtomicRefCell` but without runtime checks in any build profile.
+#[repr(transparent)]
+pub struct UncheckedCell<T: ?Sized> {
+    inner: UnsafeCell<T>,
+}
+
+impl<T> UncheckedCell<T> {
+    /// Creates a new `UncheckedCell` containing `value`.
+    #[inline]
+    pub const fn new(value: T) -> UncheckedCell<T> {
+        UncheckedCell {
+            inner: UnsafeCell::new(value),
+        }
+    }
+
+    /// Consumes the `UncheckedCell`, returning the wrapped value.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+}
+
+impl<T: ?Sized> UncheckedCell<T> {
+    /// Consumes a boxed `UncheckedCell`, returning the wrapped value, boxed, in the same
+    /// allocation. Like `MaybeAtomicRefCell::into_inner_boxed`.
+    #[inline]
+    pub fn into_inner_boxed(self: Box<Self>) -> Box<T> {
+        // SAFETY: `UncheckedCell<T>` is `repr(transparent)` over `UnsafeCell<T>`, which has the
+        // same memory layout as `T`.
+        unsafe { Box::from_raw(Box::into_raw(self) as *mut T) }
+    }
+
+    /// Boxes an `UncheckedCell` containing a boxed, possibly unsized, value, in the same
+    /// allocation. Like `MaybeAtomicRefCell::from_boxed`.
+    #[inline]
+    pub fn from_boxed(value: Box<T>) -> Box<UncheckedCell<T>> {
+        // SAFETY: See `into_inner_boxed`.
+        unsafe { Box::from_raw(Box::into_raw(value) as *mut UncheckedCell<T>) }
+    }
+
+    /// Immutably borrows the wrapped value, without runtime checks.
+    ///
+    /// # Safety
+    ///
+    /// The value must not be mutably borrowed for the lifetime of the returned guard.
+    #[inline]
+    pub unsafe fn borrow(&self) -> MaybeAtomicRef<'_, T, UncheckedBorrow> {
+        unsafe { borrow_unchecked(self.inner.get()) }
+    }
+
+    /// Mutably borrows the wrapped value, without runtime checks.
+    ///
+    /// # Safety
+    ///
+    /// The value must not be borrowed at all for the lifetime of the returned guard.
+    #[inline]
+    pub unsafe fn borrow_mut(&self) -> MaybeAtomicRefMut<'_, T, UncheckedBorrow> {
+        unsafe { borrow_mut_unchecked(self.inner.get()) }
+    }
+
+    /// Returns a raw pointer to the underlying data in this cell.
+    #[inline]
+    pub fn as_ptr(&self) -> *mut T {
+        self.inner.get()
+    }
+
+    /// Returns a mutable reference to the wrapped value.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+}
+
+unsafe impl<T: ?Sized + Send> Send for UncheckedCell<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for UncheckedCell<T> {}
+
+impl<T: Default> Default for UncheckedCell<T> {
+    #[inline]
+    fn default() -> UncheckedCell<T> {
+        UncheckedCell::new(T::default())
+    }
+}
+
+impl<T> From<T> for UncheckedCell<T> {
+    fn from(t: T) -> UncheckedCell<T> {
+        UncheckedCell::new(t)
+    }
+}
+
+impl<T: ?Sized> Debug for UncheckedCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UncheckedCell")
+            .field("value", &format_args!("<unchecked>"))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CheckedAtomicRefCell, MaybeAtomicRef, MaybeAtomicRefMut, UncheckedCell};
+
+    #[test]
+    fn it_works() {
+        struct Fields {
+            checked: CheckedAtomicRefCell<u32>,
+            unchecked: UncheckedCell<u32>,
+        }
+
+        fn bump<B>(mut guard: MaybeAtomicRefMut<'_, u32, B>) {
+            *guard += 1;
+        }
+
+        let fields = Fields {
+            checked: CheckedAtomicRefCell::new(1),
+            unchecked: UncheckedCell::new(1),
+        };
+        bump(fields.checked.borrow_mut());
+        bump(unsafe { fields.unchecked.borrow_mut() });
+
+        let checked = MaybeAtomicRef::map(fields.checked.borrow(), |value| value);
+        let unchecked = unsafe { fields.unchecked.borrow() };
+        assert_eq!(checked, unchecked);
+        assert_eq!(format!("{:?} {}", checked, unchecked), "2 2");
+        drop((checked, unchecked));
+
+        assert_eq!(fields.checked.into_inner(), 2);
+        assert_eq!(fields.unchecked.into_inner(), 2);
+    }
+
+    #[test]
+    fn try_borrow() {
+        let cell = CheckedAtomicRefCell::new(1);
+        let reading = cell.try_borrow().unwrap();
+        assert!(cell.try_borrow_mut().is_none());
+        assert_eq!(*cell.try_borrow().unwrap(), *reading);
+        drop(reading);
+        let mut writing = cell.try_borrow_mut().unwrap();
+        *writing += 1;
+        // Doesn't wait for the other thread, even with the `blocking` backend.
+        std::thread::scope(|s| {
+            s.spawn(|| assert!(cell.try_borrow().is_none() && cell.try_borrow_mut().is_none()));
+        });
+        drop(writing);
+        assert_eq!(cell.into_inner(), 2);
+    }
+
+    #[test]
+    fn boxed() {
+        let checked = CheckedAtomicRefCell::from_boxed(Box::new([1u8, 2]));
+        let checked: Box<CheckedAtomicRefCell<[u8]>> = checked;
+        checked.borrow_mut()[0] = 3;
+        let unchecked = UncheckedCell::from_boxed(checked.into_inner_boxed());
+        unsafe { unchecked.borrow_mut()[1] = 4 };
+        assert_eq!(*unchecked.into_inner_boxed(), [3, 4]);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[should_panic(expected = "already borrowed by this thread (reentrant borrow)")]
+    fn it_panics_checked() {
+        let cell = CheckedAtomicRefCell::new(5);
+        let _borrow1 = cell.borrow();
+        let _borrow2 = cell.borrow_mut();
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[should_panic(expected = "still borrowed by this thread")]
+    fn it_panics_not_borrowed_checked() {
+        let cell = CheckedAtomicRefCell::new(5);
+        cell.assert_not_borrowed();
+        let _borrow = cell.borrow_mut();
+        cell.assert_not_borrowed();
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[should_panic(expected = "still mutably borrowed by this thread")]
+    fn it_panics_not_mutably_borrowed_checked() {
+        let cell = CheckedAtomicRefCell::new(5);
+        let borrow = cell.borrow();
+        cell.assert_not_mutably_borrowed();
+        drop(borrow);
+        let _borrow = cell.borrow_mut();
+        cell.assert_not_mutably_borrowed();
+    }
+}
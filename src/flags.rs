@@ -0,0 +1,104 @@
+//! Compact borrow flags, for values whose borrows are tracked in a table shared by many of them,
+//! rather than by a `BorrowCell` each.
+//!
+//! Unlike the `blocking` backend, a conflicting borrow never waits for the flag to be released.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// The number of immutable borrows of a value, or `EXCLUSIVE`.
+pub(crate) struct BorrowFlag(AtomicU32);
+
+const EXCLUSIVE: u32 = u32::MAX;
+
+/// An immutable borrow of a `BorrowFlag`, released on drop.
+pub(crate) struct Shared<'b>(&'b BorrowFlag);
+
+/// A mutable borrow of a `BorrowFlag`, released on drop.
+pub(crate) struct Exclusive<'b>(&'b BorrowFlag);
+
+impl BorrowFlag {
+    #[inline]
+    pub(crate) const fn new() -> BorrowFlag {
+        BorrowFlag(AtomicU32::new(0))
+    }
+
+    /// Immutably borrows the value, if it isn't mutably borrowed.
+    #[inline]
+    pub(crate) fn try_borrow(&self) -> Option<Shared<'_>> {
+        self.acquire_shared().then(|| Shared(self))
+    }
+
+    /// Mutably borrows the value, if it isn't borrowed at all.
+    #[inline]
+    pub(crate) fn try_borrow_mut(&self) -> Option<Exclusive<'_>> {
+        self.0
+            .compare_exchange(0, EXCLUSIVE, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+            .then(|| Exclusive(self))
+    }
+
+    /// Releases an immutable borrow whose `Shared` was forgotten.
+    ///
+    /// # Safety
+    ///
+    /// The value must be immutably borrowed, by a forgotten `Shared`.
+    #[cfg(feature = "lock_api")]
+    #[inline]
+    pub(crate) unsafe fn release_shared(&self) {
+        drop(Shared(self));
+    }
+
+    /// Releases a mutable borrow whose `Exclusive` was forgotten.
+    ///
+    /// # Safety
+    ///
+    /// The value must be mutably borrowed, by a forgotten `Exclusive`.
+    #[cfg(feature = "lock_api")]
+    #[inline]
+    pub(crate) unsafe fn release_exclusive(&self) {
+        drop(Exclusive(self));
+    }
+
+    #[inline]
+    fn acquire_shared(&self) -> bool {
+        let mut count = self.0.load(Ordering::Relaxed);
+        loop {
+            if count == EXCLUSIVE {
+                return false;
+            }
+            assert!(count < EXCLUSIVE - 1, "too many immutable borrows");
+            match self.0.compare_exchange_weak(
+                count,
+                count + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(actual) => count = actual,
+            }
+        }
+    }
+}
+
+impl Clone for Shared<'_> {
+    #[inline]
+    fn clone(&self) -> Self {
+        let acquired = self.0.acquire_shared();
+        debug_assert!(acquired, "mutably borrowed alongside an immutable borrow");
+        Shared(self.0)
+    }
+}
+
+impl Drop for Shared<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        self.0 .0.fetch_sub(1, Ordering::Release);
+    }
+}
+
+impl Drop for Exclusive<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        self.0 .0.store(0, Ordering::Release);
+    }
+}
@@ -0,0 +1,81 @@
+//! A read-only counterpart to `MaybeAtomicRefCell`, for values that are built and then only
+//! read.
+
+use crate::MaybeAtomicRefCell;
+use std::ops::Deref;
+
+/// A value that can no longer be mutated, so it can be shared and read without any checks or
+/// `unsafe`, in every build. Made by `MaybeAtomicRefCell::freeze`.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct Frozen<T: ?Sized>(T);
+
+impl<T> Frozen<T> {
+    /// Creates a new `Frozen` containing `value`.
+    #[inline]
+    pub const fn new(value: T) -> Frozen<T> {
+        Frozen(value)
+    }
+
+    /// Consumes the `Frozen`, returning the wrapped value.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+
+    /// Consumes the `Frozen`, returning a `MaybeAtomicRefCell` so the value can be mutated
+    /// again.
+    #[inline]
+    pub fn thaw(self) -> MaybeAtomicRefCell<T> {
+        MaybeAtomicRefCell::new(self.0)
+    }
+}
+
+impl<T: ?Sized> Deref for Frozen<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: ?Sized> AsRef<T> for Frozen<T> {
+    #[inline]
+    fn as_ref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> From<T> for Frozen<T> {
+    #[inline]
+    fn from(value: T) -> Frozen<T> {
+        Frozen(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::MaybeAtomicRefCell;
+
+    #[test]
+    fn it_works() {
+        let cell = MaybeAtomicRefCell::new(vec![1, 2]);
+        unsafe {
+            cell.borrow_mut().push(3);
+        }
+
+        let frozen = cell.freeze();
+        std::thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| assert_eq!(frozen.iter().sum::<i32>(), 6));
+            }
+        });
+
+        let cell = frozen.thaw();
+        unsafe {
+            cell.borrow_mut().push(4);
+        }
+        assert_eq!(cell.into_inner(), [1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,152 @@
+//! Generations of cells, replaced whenever a checked mutable borrow of them is released, so that
+//! cached pointers can be checked for staleness. Generations are drawn from a global counter, so
+//! a cell never reuses the generation of another cell that was at the same address before it.
+
+use crate::fixed;
+use crate::hook::WriteEvent;
+use crate::reentrancy;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// The generations of the values that `current` was called for, keyed by address and type name
+/// (so that a cell at the start of another cell's value isn't mistaken for it).
+static GENERATIONS: Mutex<BTreeMap<(usize, &'static str), u64>> = Mutex::new(BTreeMap::new());
+
+/// Whether `GENERATIONS` is non-empty, so that releasing a borrow (or dropping a cell) doesn't
+/// lock it otherwise. Only changed with `GENERATIONS` locked.
+static ANY: AtomicBool = AtomicBool::new(false);
+
+/// The next generation to hand out.
+static NEXT: AtomicU64 = AtomicU64::new(0);
+
+#[inline]
+fn next() -> u64 {
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+// The mutex is never held while running user code, so poisoning is impossible in practice.
+#[inline]
+fn lock() -> MutexGuard<'static, BTreeMap<(usize, &'static str), u64>> {
+    GENERATIONS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Returns the generation of the value behind `ptr`, counting its mutable borrows from now on.
+#[inline]
+pub(crate) fn current<T: ?Sized>(ptr: *const T) -> u64 {
+    let key = (reentrancy::address(ptr), std::any::type_name::<T>());
+    let mut generations = lock();
+    let generation = *generations.entry(key).or_insert_with(next);
+    ANY.store(true, Ordering::Relaxed);
+    generation
+}
+
+/// Replaces the generation, if counted, of the value whose mutable borrow `event` describes.
+#[inline]
+pub(crate) fn bump(event: &WriteEvent) {
+    if !ANY.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Some(generation) = lock().get_mut(&(event.address, event.type_name)) {
+        *generation = next();
+    }
+}
+
+/// Stops counting the generation of the value behind `ptr`, whose cell is going away.
+#[inline]
+pub(crate) fn remove<T: ?Sized>(ptr: *const T) {
+    if !ANY.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut generations = lock();
+    generations.remove(&(reentrancy::address(ptr), std::any::type_name::<T>()));
+    ANY.store(!generations.is_empty(), Ordering::Relaxed);
+}
+
+/// Panics if `ptr` and `generation`, returned by `as_versioned_ptr`, are stale for the value
+/// behind `current`.
+#[inline]
+#[track_caller]
+pub(crate) fn validate<T: ?Sized>(current: *const T, ptr: *const T, generation: u64) {
+    if !std::ptr::addr_eq(ptr, current) {
+        fixed::violation(format_args!(
+            "stale pointer: the value moved from {:p} to {:p}",
+            ptr, current
+        ));
+    }
+    let now = self::current(current);
+    if generation != now {
+        fixed::violation(format_args!(
+            "stale pointer: generation {} but the value was mutably borrowed or replaced since \
+            (now generation {})",
+            generation, now
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::lock;
+    use crate::reentrancy;
+    use crate::{CheckedAtomicRefCell, MaybeAtomicRefCell};
+
+    #[test]
+    fn it_works() {
+        let cell = CheckedAtomicRefCell::new(1);
+        let (ptr, generation) = cell.as_versioned_ptr();
+        cell.validate(ptr, generation);
+        drop(cell.borrow());
+        cell.validate(ptr, generation);
+        *cell.borrow_mut() += 1;
+        let (ptr, later) = cell.as_versioned_ptr();
+        assert_ne!(later, generation);
+        cell.validate(ptr, later);
+
+        let boxed = Box::new(MaybeAtomicRefCell::new(1));
+        let (ptr, generation) = boxed.as_versioned_ptr();
+        boxed.debug_validate(ptr, generation);
+    }
+
+    #[test]
+    fn removed_on_drop() {
+        let key =
+            |ptr: *const Vec<u8>| (reentrancy::address(ptr), std::any::type_name::<Vec<u8>>());
+
+        let boxed = Box::new(CheckedAtomicRefCell::new(vec![1u8]));
+        let (ptr, generation) = boxed.as_versioned_ptr();
+        assert!(lock().contains_key(&key(ptr)));
+        drop(boxed);
+        assert!(!lock().contains_key(&key(ptr)));
+
+        // A later cell at the same address starts over with a different generation.
+        let boxed = Box::new(CheckedAtomicRefCell::new(vec![2u8]));
+        assert_ne!(boxed.as_versioned_ptr().1, generation);
+
+        let (ptr, _) = boxed.as_versioned_ptr();
+        assert_eq!(*boxed.into_inner_boxed(), [2]);
+        assert!(!lock().contains_key(&key(ptr)));
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[should_panic(expected = "stale pointer: generation")]
+    fn it_panics_mutated() {
+        let cell = CheckedAtomicRefCell::new(1);
+        let (ptr, generation) = cell.as_versioned_ptr();
+        *cell.borrow_mut() += 1;
+        cell.validate(ptr, generation);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(
+        any(debug_assertions, feature = "safe"),
+        should_panic(expected = "stale pointer: the value moved")
+    )]
+    fn it_panics_moved() {
+        let cell = MaybeAtomicRefCell::new(1);
+        let (ptr, generation) = cell.as_versioned_ptr();
+        let moved = Box::new(cell);
+        moved.debug_validate(ptr, generation);
+    }
+}
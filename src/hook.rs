@@ -0,0 +1,95 @@
+//! A global hook called whenever a checked mutable borrow is released, for tracing which code
+//! last mutated a value.
+
+use std::panic::Location;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+/// The `fn(&WriteEvent)` set by `set_on_write`, or null.
+static ON_WRITE: AtomicPtr<()> = AtomicPtr::new(std::ptr::null_mut());
+
+/// A released mutable borrow, as passed to the hook set by `set_on_write`.
+#[derive(Clone, Copy, Debug)]
+#[non_exhaustive]
+pub struct WriteEvent {
+    /// The type of the value in the cell.
+    pub type_name: &'static str,
+    /// The address of the value in the cell.
+    pub address: usize,
+    /// Where the value was mutably borrowed.
+    pub location: &'static Location<'static>,
+}
+
+/// Sets (or, with `None`, removes) the hook called whenever a mutable borrow is released, after
+/// the value can be borrowed again.
+///
+/// Only checked borrows call the hook, so it is never called in release mode (without the `safe`
+/// feature), except by `CheckedAtomicRefCell`. A mutable borrow split between several guards
+/// calls it once, when the last of them is dropped.
+///
+/// ```
+/// use maybe_atomic_refcell::{set_on_write, MaybeAtomicRefCell, WriteEvent};
+///
+/// fn trace(event: &WriteEvent) {
+///     eprintln!("{} at {:#x} written at {}", event.type_name, event.address, event.location);
+/// }
+///
+/// set_on_write(Some(trace));
+/// let cell = MaybeAtomicRefCell::new(1);
+/// unsafe {
+///     *cell.borrow_mut() += 1;
+/// }
+/// set_on_write(None);
+/// ```
+#[inline]
+pub fn set_on_write(hook: Option<fn(&WriteEvent)>) {
+    let hook = hook.map_or(std::ptr::null_mut(), |hook| hook as *mut ());
+    ON_WRITE.store(hook, Ordering::Release);
+}
+
+/// Calls the hook set by `set_on_write`, if any.
+#[inline]
+pub(crate) fn on_write(event: &WriteEvent) {
+    let hook = ON_WRITE.load(Ordering::Acquire);
+    if !hook.is_null() {
+        // SAFETY: Only `fn(&WriteEvent)`s are stored, by `set_on_write`.
+        let hook = unsafe { std::mem::transmute::<*mut (), fn(&WriteEvent)>(hook) };
+        hook(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{set_on_write, CheckedAtomicRefCell, MaybeAtomicRefMut, WriteEvent};
+    use std::sync::Mutex;
+
+    static EVENTS: Mutex<Vec<(&str, u32)>> = Mutex::new(Vec::new());
+
+    #[test]
+    fn it_works() {
+        fn record(event: &WriteEvent) {
+            if event.type_name.ends_with("Marker") {
+                EVENTS
+                    .lock()
+                    .unwrap()
+                    .push((event.location.file(), event.location.line()));
+            }
+        }
+
+        struct Marker(u32, u32);
+
+        set_on_write(Some(record));
+        let cell = CheckedAtomicRefCell::new(Marker(1, 2));
+        let line = line!() + 1;
+        let guard = cell.borrow_mut();
+        let (mut a, mut b) = MaybeAtomicRefMut::map_split(guard, |m| (&mut m.0, &mut m.1));
+        *a += 1;
+        drop(a);
+        assert!(EVENTS.lock().unwrap().is_empty());
+        *b += 1;
+        drop(b);
+        set_on_write(None);
+        drop(cell.borrow_mut());
+
+        assert_eq!(*EVENTS.lock().unwrap(), [(file!(), line)]);
+    }
+}
@@ -0,0 +1,165 @@
+//! Invariants of cells, checked whenever a checked mutable borrow of them is released, to catch
+//! corruption at the mutation that caused it rather than at some later read.
+
+use crate::hook::WriteEvent;
+use crate::reentrancy;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// An invariant of the value at `address`, which must be of the type named `type_name` (so that
+/// a cell at the start of another cell's value isn't mistaken for it). The check is passed a
+/// pointer to the value, which is of that type.
+struct Invariant {
+    address: usize,
+    type_name: &'static str,
+    check: Arc<dyn Fn(*const ()) + Send + Sync>,
+}
+
+static INVARIANTS: Mutex<Vec<Invariant>> = Mutex::new(Vec::new());
+
+/// Whether `INVARIANTS` is non-empty, so that releasing a borrow (or dropping a cell) doesn't
+/// lock it otherwise.
+static ANY: AtomicBool = AtomicBool::new(false);
+
+// The mutex is never held while running user code, so poisoning is impossible in practice.
+#[inline]
+fn lock() -> std::sync::MutexGuard<'static, Vec<Invariant>> {
+    INVARIANTS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Sets the invariant of the value behind `ptr`, replacing any previous one.
+///
+/// # Safety
+///
+/// The value must not be moved until the invariant is removed, with `clear` or by dropping its
+/// cell, since another value could otherwise take its place, of a type with the same name but
+/// not the same lifetimes.
+pub(crate) unsafe fn set<T: 'static>(
+    ptr: *const T,
+    invariant: impl Fn(&T) + Send + Sync + 'static,
+) {
+    let check = Arc::new(move |value: *const ()| {
+        // SAFETY: Only called by `check`, with the value of a mutable borrow that is released
+        // by this thread, after its guards are gone, which is still the value the invariant was
+        // set for (upheld by the caller of `set`).
+        invariant(unsafe { &*(value as *const T) })
+    });
+    clear(ptr);
+    let mut invariants = lock();
+    invariants.push(Invariant {
+        address: reentrancy::address(ptr),
+        type_name: std::any::type_name::<T>(),
+        check,
+    });
+    ANY.store(true, Ordering::Relaxed);
+}
+
+/// Removes the invariant of the value behind `ptr`, if any.
+#[inline]
+pub(crate) fn clear<T: ?Sized>(ptr: *const T) {
+    if !ANY.load(Ordering::Relaxed) {
+        return;
+    }
+    let address = reentrancy::address(ptr);
+    let type_name = std::any::type_name::<T>();
+    let mut invariants = lock();
+    invariants.retain(|i| (i.address, i.type_name) != (address, type_name));
+    ANY.store(!invariants.is_empty(), Ordering::Relaxed);
+}
+
+/// Checks the invariant, if any, of the value whose mutable borrow `event` describes, which
+/// must still be held.
+#[inline]
+pub(crate) fn check(event: &WriteEvent) {
+    if !ANY.load(Ordering::Relaxed) {
+        return;
+    }
+    let check = lock()
+        .iter()
+        .find(|i| (i.address, i.type_name) == (event.address, event.type_name))
+        .map(|i| Arc::clone(&i.check));
+    if let Some(check) = check {
+        check(std::ptr::with_exposed_provenance(event.address));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::lock;
+    use crate::{reentrancy, CheckedAtomicRefCell, MaybeAtomicRefCell, MaybeAtomicRefMut};
+    use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+
+    fn sorted(v: &Vec<u32>) {
+        assert!(v.windows(2).all(|w| w[0] <= w[1]), "not sorted: {:?}", v);
+    }
+
+    #[test]
+    fn it_works() {
+        let cell = CheckedAtomicRefCell::new(vec![1, 3]);
+        unsafe {
+            cell.set_invariant(sorted);
+        }
+        cell.borrow_mut().insert(1, 2);
+        let (mut a, mut b) = MaybeAtomicRefMut::map_split(cell.borrow_mut(), |v| {
+            let (a, b) = v.split_at_mut(2);
+            (&mut a[0], &mut b[0])
+        });
+        // Temporarily broken, but restored before the borrow is released.
+        *b = 0;
+        *a = 0;
+        drop(a);
+        *b = 4;
+        drop(b);
+        cell.clear_invariant();
+        cell.borrow_mut().reverse();
+        assert_eq!(cell.into_inner(), [4, 2, 0]);
+    }
+
+    #[test]
+    fn removed_on_drop() {
+        let cell = Box::new(CheckedAtomicRefCell::new(vec![2, 1]));
+        unsafe {
+            cell.set_invariant(sorted);
+        }
+        let address = reentrancy::address(cell.as_ptr());
+        let set = || lock().iter().any(|i| i.address == address);
+        assert!(set());
+        drop(cell);
+        assert!(!set());
+
+        // Nor does it apply to a later cell at the same address.
+        let cell = Box::new(CheckedAtomicRefCell::new(vec![1]));
+        cell.borrow_mut().insert(0, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "not sorted: [3, 1]")]
+    fn it_panics_checked() {
+        let cell = CheckedAtomicRefCell::new(vec![1]);
+        unsafe {
+            cell.set_invariant(sorted);
+        }
+        let result = catch_unwind(AssertUnwindSafe(|| cell.borrow_mut().insert(0, 3)));
+        cell.clear_invariant();
+        // The borrow was released despite the panic.
+        drop(cell.borrow_mut());
+        resume_unwind(result.unwrap_err());
+    }
+
+    #[test]
+    #[cfg_attr(
+        any(debug_assertions, feature = "safe"),
+        should_panic(expected = "not sorted")
+    )]
+    fn it_panics_maybe() {
+        let cell = MaybeAtomicRefCell::new(vec![1]);
+        unsafe {
+            cell.set_invariant(sorted);
+        }
+        let result = catch_unwind(AssertUnwindSafe(|| unsafe { cell.borrow_mut().push(0) }));
+        cell.clear_invariant();
+        if let Err(panic) = result {
+            resume_unwind(panic);
+        }
+    }
+}
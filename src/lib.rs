@@ -1,22 +1,120 @@
+#![cfg_attr(feature = "nightly", feature(coerce_unsized, unsize, must_not_suspend))]
+
+use std::any::Any;
+use std::borrow::{Borrow, BorrowMut};
+use std::cmp::Ordering;
 use std::fmt;
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+#[cfg(feature = "nightly")]
+use std::marker::Unsize;
+use std::mem::MaybeUninit;
+#[cfg(feature = "nightly")]
+use std::ops::CoerceUnsized;
 use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+
+mod backend;
+mod buffer;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod exclusive;
+mod fixed;
+#[cfg(any(debug_assertions, feature = "safe", feature = "lock_api"))]
+mod flags;
+mod frozen;
+mod generation;
+mod hook;
+mod invariant;
+pub mod prelude;
+#[cfg(feature = "lock_api")]
+mod raw_lock;
+mod reentrancy;
+mod statics;
+pub mod storage;
+mod tuple;
+
+pub use buffer::{MaybeDoubleBuffer, MaybeTripleBuffer};
+pub use exclusive::MaybeExclusive;
+pub use fixed::{
+    CheckedAtomicRefCell, CheckedBorrow, CheckedBorrowMut, SplitBorrow, UncheckedBorrow,
+    UncheckedCell,
+};
+pub use frozen::Frozen;
+pub use hook::{set_on_write, WriteEvent};
+#[cfg(feature = "lock_api")]
+pub use raw_lock::{MaybeCondvar, MaybeMutex, MaybeRawMutex, MaybeRawRwLock, MaybeRwLock};
+#[cfg(feature = "borrow_tracking")]
+pub use reentrancy::assert_all_quiescent;
+pub use statics::StaticMaybeAtomicRefCell;
+pub use tuple::{borrow_all, BorrowAll};
+
+#[cfg(feature = "derive")]
+pub use maybe_atomic_refcell_derive::MaybeAtomicFields;
+
+// Lets the derive macro's `::maybe_atomic_refcell` paths resolve in this crate's tests.
+#[cfg(all(test, feature = "derive"))]
+extern crate self as maybe_atomic_refcell;
 
 /// Like an `AtomicRefCell` but no overhead of runtime checks in release mode.
+///
+/// In release mode (without the `safe` feature), `MaybeAtomicRefCell<T>` is guaranteed to be
+/// `repr(transparent)` over `UnsafeCell<T>`, so it has the same memory layout as `T`. With the
+/// `side_table` feature, it has that layout in every build, since the runtime checks keep their
+/// state in a global table instead. The `change_detection` feature voids these guarantees, as
+/// the cell then also holds its dirty flag.
+#[cfg_attr(not(feature = "change_detection"), repr(transparent))]
+#[cfg_attr(feature = "change_detection", repr(C))]
 pub struct MaybeAtomicRefCell<T: ?Sized> {
+    #[cfg(feature = "change_detection")]
+    changed: std::sync::atomic::AtomicBool,
     #[cfg(any(debug_assertions, feature = "safe"))]
-    inner: atomic_refcell::AtomicRefCell<T>,
+    inner: backend::BorrowCell<T>,
     #[cfg(not(any(debug_assertions, feature = "safe")))]
     inner: std::cell::UnsafeCell<T>,
 }
 
+// The layout guarantees above, checked at compile time for some representative types.
+#[cfg(all(
+    any(not(any(debug_assertions, feature = "safe")), feature = "side_table"),
+    not(feature = "change_detection")
+))]
+const _: () = {
+    use std::mem::{align_of, size_of};
+    assert!(size_of::<MaybeAtomicRefCell<u8>>() == size_of::<u8>());
+    assert!(size_of::<MaybeAtomicRefCell<u64>>() == size_of::<u64>());
+    assert!(align_of::<MaybeAtomicRefCell<u64>>() == align_of::<u64>());
+    assert!(size_of::<MaybeAtomicRefCell<()>>() == 0);
+    assert!(size_of::<MaybeAtomicRefCell<String>>() == size_of::<String>());
+    assert!(size_of::<&MaybeAtomicRefCell<[u32]>>() == size_of::<&[u32]>());
+    assert!(size_of::<[MaybeAtomicRefCell<u16>; 3]>() == size_of::<[u16; 3]>());
+};
+
+// Unchecked guards are just a pointer, like a reference (including its niche).
+const _: () = {
+    use std::mem::size_of;
+    assert!(size_of::<MaybeAtomicRef<'static, u64, UncheckedBorrow>>() == size_of::<&u64>());
+    assert!(size_of::<MaybeAtomicRefMut<'static, u64, UncheckedBorrow>>() == size_of::<&u64>());
+    assert!(size_of::<MaybeAtomicRef<'static, [u8], UncheckedBorrow>>() == size_of::<&[u8]>());
+    assert!(
+        size_of::<MaybeAtomicRefMut<'static, dyn Any, UncheckedBorrow>>() == size_of::<&dyn Any>()
+    );
+    assert!(
+        size_of::<Option<MaybeAtomicRef<'static, u64, UncheckedBorrow>>>()
+            == size_of::<Option<&u64>>()
+    );
+};
+
 impl<T> MaybeAtomicRefCell<T> {
     /// Creates a new `MaybeAtomicRefCell` containing `value`.
     #[inline]
     pub const fn new(value: T) -> MaybeAtomicRefCell<T> {
         MaybeAtomicRefCell {
+            #[cfg(feature = "change_detection")]
+            changed: std::sync::atomic::AtomicBool::new(false),
             #[cfg(any(debug_assertions, feature = "safe"))]
-            inner: atomic_refcell::AtomicRefCell::new(value),
+            inner: backend::BorrowCell::new(value),
             #[cfg(not(any(debug_assertions, feature = "safe")))]
             inner: std::cell::UnsafeCell::new(value),
         }
@@ -27,245 +125,2991 @@ impl<T> MaybeAtomicRefCell<T> {
     pub fn into_inner(self) -> T {
         self.inner.into_inner()
     }
+
+    /// Consumes an `Arc` of a `MaybeAtomicRefCell`, returning the wrapped value if it is the only
+    /// `Arc` pointing to the cell, like `Arc::try_unwrap` followed by `into_inner`, or the `Arc`
+    /// otherwise.
+    #[inline]
+    pub fn try_unwrap_into_inner(
+        arc: std::sync::Arc<MaybeAtomicRefCell<T>>,
+    ) -> Result<T, std::sync::Arc<MaybeAtomicRefCell<T>>> {
+        std::sync::Arc::try_unwrap(arc).map(MaybeAtomicRefCell::into_inner)
+    }
+
+    /// Consumes the `MaybeAtomicRefCell`, returning an `AtomicRefCell` containing the wrapped
+    /// value.
+    #[cfg(feature = "atomic_refcell")]
+    #[inline]
+    pub fn into_atomic_ref_cell(self) -> atomic_refcell::AtomicRefCell<T> {
+        #[cfg(any(debug_assertions, feature = "safe"))]
+        return self.inner.into_atomic_ref_cell();
+        #[cfg(not(any(debug_assertions, feature = "safe")))]
+        atomic_refcell::AtomicRefCell::new(self.into_inner())
+    }
+
+    /// Sets an invariant of the value, which is checked whenever a mutable borrow of it is
+    /// released, in debug mode (or with the `safe` feature). Does nothing in release mode.
+    ///
+    /// This catches corruption at the mutation that caused it, rather than at some later read.
+    /// The invariant runs before the value can be borrowed again (but after the guards are
+    /// dropped, so it can be broken temporarily), and should panic if it doesn't hold. A
+    /// mutable borrow split between several guards is checked once, when the last of them is
+    /// dropped. Setting another invariant replaces this one.
+    ///
+    /// ```
+    /// use maybe_atomic_refcell::MaybeAtomicRefCell;
+    ///
+    /// let cell = MaybeAtomicRefCell::new(vec![1, 2]);
+    /// unsafe {
+    ///     cell.set_invariant(|v| assert!(v.is_sorted()));
+    ///     cell.borrow_mut().push(3);
+    /// }
+    /// cell.clear_invariant();
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The cell must not be moved until the invariant is removed, with `clear_invariant` or by
+    /// dropping the cell, since it is kept by address.
+    #[inline]
+    pub unsafe fn set_invariant(&self, invariant: impl Fn(&T) + Send + Sync + 'static)
+    where
+        T: 'static,
+    {
+        #[cfg(any(debug_assertions, feature = "safe"))]
+        unsafe {
+            invariant::set(self.as_ptr(), invariant)
+        }
+        #[cfg(not(any(debug_assertions, feature = "safe")))]
+        drop(invariant);
+    }
+
+    /// Removes the invariant set by `set_invariant`, if any.
+    #[inline]
+    pub fn clear_invariant(&self) {
+        #[cfg(any(debug_assertions, feature = "safe"))]
+        invariant::clear(self.as_ptr());
+    }
+
+    /// Consumes the `MaybeAtomicRefCell`, returning a `Frozen` that can be read without any
+    /// checks or `unsafe`, since it can't be mutated.
+    #[inline]
+    pub fn freeze(self) -> Frozen<T> {
+        Frozen::new(self.into_inner())
+    }
+
+    /// Consumes the `MaybeAtomicRefCell`, returning a `RefCell` containing the wrapped value.
+    #[inline]
+    pub fn into_ref_cell(self) -> std::cell::RefCell<T> {
+        std::cell::RefCell::new(self.into_inner())
+    }
+
+    /// Consumes a boxed `MaybeAtomicRefCell`, returning a raw pointer to the wrapped value
+    /// allocated like a `Box<T>`. Use `from_raw` to reassemble the cell.
+    ///
+    /// In release mode, this reuses the allocation. In debug mode (or with the `change_detection`
+    /// feature), the value is moved to a new allocation, since the cell's allocation also holds
+    /// its borrow state (or dirty flag).
+    #[inline]
+    #[allow(clippy::boxed_local)]
+    pub fn into_raw(cell: Box<MaybeAtomicRefCell<T>>) -> *mut T {
+        #[cfg(any(debug_assertions, feature = "safe", feature = "change_detection"))]
+        return Box::into_raw(Box::new(cell.into_inner()));
+        // `MaybeAtomicRefCell<T>` is `repr(transparent)` over `UnsafeCell<T>`, which has the same
+        // memory layout as `T`.
+        #[cfg(not(any(debug_assertions, feature = "safe", feature = "change_detection")))]
+        {
+            Box::into_raw(cell) as *mut T
+        }
+    }
+
+    /// Reassembles a boxed `MaybeAtomicRefCell` from a pointer returned by `into_raw`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `into_raw` or `Box::<T>::into_raw`, and must not be used
+    /// afterwards.
+    #[inline]
+    pub unsafe fn from_raw(ptr: *mut T) -> Box<MaybeAtomicRefCell<T>> {
+        #[cfg(any(debug_assertions, feature = "safe", feature = "change_detection"))]
+        return Box::new(MaybeAtomicRefCell::new(*unsafe { Box::from_raw(ptr) }));
+        #[cfg(not(any(debug_assertions, feature = "safe", feature = "change_detection")))]
+        unsafe {
+            Box::from_raw(ptr as *mut MaybeAtomicRefCell<T>)
+        }
+    }
+}
+
+impl<T> MaybeAtomicRefCell<T> {
+    /// Boxes a `MaybeAtomicRefCell` containing a boxed value. The reverse of `into_inner_boxed`,
+    /// except that the value must be sized, as the checked layout of an unsized cell is up to
+    /// the backend. A boxed cell of a sized value can still be unsized afterwards.
+    ///
+    /// In release mode, this reuses the allocation. In debug mode (or with the `change_detection`
+    /// feature), the value is moved to a new allocation.
+    ///
+    /// ```
+    /// use maybe_atomic_refcell::MaybeAtomicRefCell;
+    ///
+    /// let cell = MaybeAtomicRefCell::from_boxed(Box::new([1, 2]));
+    /// let cell: Box<MaybeAtomicRefCell<[u8]>> = cell;
+    /// assert_eq!(*cell.into_inner_boxed(), [1, 2]);
+    /// ```
+    #[inline]
+    #[allow(clippy::boxed_local)]
+    pub fn from_boxed(value: Box<T>) -> Box<MaybeAtomicRefCell<T>> {
+        #[cfg(any(debug_assertions, feature = "safe", feature = "change_detection"))]
+        return Box::new(MaybeAtomicRefCell::new(*value));
+        // SAFETY: `MaybeAtomicRefCell<T>` is `repr(transparent)` over `UnsafeCell<T>`, which has
+        // the same memory layout as `T`.
+        #[cfg(not(any(debug_assertions, feature = "safe", feature = "change_detection")))]
+        unsafe {
+            Box::from_raw(Box::into_raw(value) as *mut MaybeAtomicRefCell<T>)
+        }
+    }
 }
 
 impl<T: ?Sized> MaybeAtomicRefCell<T> {
+    /// Consumes a boxed `MaybeAtomicRefCell`, returning the wrapped value, boxed. Unlike
+    /// `into_inner`, this works for unsized values, such as `[T]` and `dyn Trait`.
+    ///
+    /// In release mode, this reuses the allocation. In debug mode (or with the `change_detection`
+    /// feature), the value is moved to a new allocation, like in `into_raw`.
+    ///
+    /// ```
+    /// use maybe_atomic_refcell::MaybeAtomicRefCell;
+    /// use std::fmt::Debug;
+    ///
+    /// let cell: Box<MaybeAtomicRefCell<dyn Debug>> = Box::new(MaybeAtomicRefCell::new(5));
+    /// let value: Box<dyn Debug> = cell.into_inner_boxed();
+    /// assert_eq!(format!("{value:?}"), "5");
+    /// ```
+    #[inline]
+    pub fn into_inner_boxed(self: Box<Self>) -> Box<T> {
+        // `MaybeAtomicRefCell<T>` is `repr(transparent)` over its field.
+        #[cfg(all(
+            any(debug_assertions, feature = "safe"),
+            not(feature = "change_detection")
+        ))]
+        return unsafe { Box::from_raw(Box::into_raw(self) as *mut backend::BorrowCell<T>) }
+            .into_inner_boxed();
+        // SAFETY: The dirty flag doesn't need dropping.
+        #[cfg(all(any(debug_assertions, feature = "safe"), feature = "change_detection"))]
+        return unsafe {
+            let cell = Box::into_raw(self);
+            backend::BorrowCell::extract_from(std::ptr::addr_of_mut!((*cell).inner), cell)
+        };
+        // SAFETY: The value is in the cell, and the dirty flag doesn't need dropping.
+        #[cfg(all(
+            not(any(debug_assertions, feature = "safe")),
+            feature = "change_detection"
+        ))]
+        return unsafe {
+            let cell = Box::into_raw(self);
+            backend::extract(cell, (*cell).inner.get())
+        };
+        #[cfg(not(any(debug_assertions, feature = "safe", feature = "change_detection")))]
+        unsafe {
+            Box::from_raw(Box::into_raw(self) as *mut T)
+        }
+    }
+
     /// Immutably borrows the wrapped value. Performs runtime checks in debug mode, but not in
     /// release mode (hence `unsafe`).
+    ///
+    /// # Safety
+    ///
+    /// The value must not be mutably borrowed for the lifetime of the returned guard.
+    ///
+    /// Note that some trait implementations (e.g. `Clone`, `PartialEq`, and `Hash`) immutably
+    /// borrow the value.
     #[inline]
-    pub unsafe fn borrow(&self) -> MaybeAtomicRef<T> {
+    #[track_caller]
+    pub unsafe fn borrow(&self) -> MaybeAtomicRef<'_, T> {
         #[cfg(any(debug_assertions, feature = "safe"))]
-        return MaybeAtomicRef {
-            inner: self.inner.borrow(),
-        };
+        return fixed::borrow(&self.inner);
         #[cfg(not(any(debug_assertions, feature = "safe")))]
         #[allow(unused_unsafe)]
-        MaybeAtomicRef {
-            inner: unsafe { &*self.inner.get() },
+        unsafe {
+            fixed::borrow_unchecked(self.inner.get())
         }
     }
 
     /// Mutably borrows the wrapped value. Performs runtime checks in debug mode, but not in
     /// release mode (hence `unsafe`).
+    ///
+    /// # Safety
+    ///
+    /// The value must not be borrowed at all for the lifetime of the returned guard.
+    ///
+    /// Note that some trait implementations (e.g. `Clone`, `PartialEq`, and `Hash`) immutably
+    /// borrow the value.
     #[inline]
-    pub unsafe fn borrow_mut(&self) -> MaybeAtomicRefMut<T> {
+    #[track_caller]
+    pub unsafe fn borrow_mut(&self) -> MaybeAtomicRefMut<'_, T> {
         #[cfg(any(debug_assertions, feature = "safe"))]
-        return MaybeAtomicRefMut {
-            inner: self.inner.borrow_mut(),
-        };
+        let guard = fixed::borrow_mut(&self.inner);
         #[cfg(not(any(debug_assertions, feature = "safe")))]
         #[allow(unused_unsafe)]
-        MaybeAtomicRefMut {
-            inner: unsafe { &mut *self.inner.get() },
-        }
+        let guard = unsafe { fixed::borrow_mut_unchecked(self.inner.get()) };
+        #[cfg(feature = "change_detection")]
+        self.mark_changed();
+        guard
     }
 
-    /// Returns a raw pointer to the underlying data in this cell.
+    /// Panics if the value is borrowed at all, in debug mode (or with the `safe` feature).
+    /// Does nothing in release mode.
     ///
-    /// External synchronization is needed to avoid data races when dereferencing
-    /// the pointer.
+    /// This is for points where every borrow should have been released, such as frame
+    /// boundaries and teardown, so that a leftover borrow is reported there rather than by the
+    /// next conflicting borrow.
     #[inline]
-    pub fn as_ptr(&self) -> *mut T {
+    #[track_caller]
+    pub fn debug_assert_not_borrowed(&self) {
         #[cfg(any(debug_assertions, feature = "safe"))]
-        return self.inner.as_ptr();
-        #[cfg(not(any(debug_assertions, feature = "safe")))]
-        self.inner.get()
+        fixed::assert_not_borrowed(&self.inner);
     }
 
-    /// Returns a mutable reference to the wrapped value.
+    /// Panics if the value is mutably borrowed, in debug mode (or with the `safe` feature).
+    /// Does nothing in release mode.
     ///
-    /// No runtime checks take place (unless debug assertions are enabled)
-    /// because this call borrows `MaybeAtomicRefCell` mutably at compile-time.
+    /// This is for code that reads through `as_ptr`, so that a conflicting `borrow_mut` guard
+    /// is reported at the moment of the read. Before writing through `as_ptr`, use
+    /// `debug_assert_not_borrowed` instead.
     #[inline]
-    pub fn get_mut(&mut self) -> &mut T {
-        self.inner.get_mut()
+    #[track_caller]
+    pub fn debug_assert_not_mutably_borrowed(&self) {
+        #[cfg(any(debug_assertions, feature = "safe"))]
+        fixed::assert_not_mutably_borrowed(&self.inner);
     }
-}
 
-unsafe impl<T: ?Sized + Send> Send for MaybeAtomicRefCell<T> {}
-unsafe impl<T: ?Sized + Send + Sync> Sync for MaybeAtomicRefCell<T> {}
+    /// Calls `f` with a reference to the wrapped value, which is immutably borrowed for the
+    /// duration of the call. Performs runtime checks in debug mode, but is a plain pointer
+    /// dereference in release mode (hence `unsafe`).
+    ///
+    /// Unlike `borrow_unguarded`, the borrow is visible to the checks of other borrows, so this
+    /// is the checked counterpart of reading through `as_ptr`.
+    ///
+    /// # Safety
+    ///
+    /// The value must not be mutably borrowed while `f` runs.
+    #[inline]
+    #[track_caller]
+    pub unsafe fn read_with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&*unsafe { self.borrow() })
+    }
 
-impl<T: Default> Default for MaybeAtomicRefCell<T> {
+    /// Calls `f` with a mutable reference to the wrapped value, which is mutably borrowed for
+    /// the duration of the call. Performs runtime checks in debug mode, but is a plain pointer
+    /// dereference in release mode (hence `unsafe`).
+    ///
+    /// Unlike `get_mut_unchecked`, the borrow is visible to the checks of other borrows, so this
+    /// is the checked counterpart of writing through `as_ptr`.
+    ///
+    /// # Safety
+    ///
+    /// The value must not be borrowed at all while `f` runs.
     #[inline]
-    fn default() -> MaybeAtomicRefCell<T> {
-        MaybeAtomicRefCell::new(Default::default())
+    #[track_caller]
+    pub unsafe fn write_with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut *unsafe { self.borrow_mut() })
     }
-}
 
-impl<T> From<T> for MaybeAtomicRefCell<T> {
-    fn from(t: T) -> MaybeAtomicRefCell<T> {
-        MaybeAtomicRefCell::new(t)
+    /// Immutably borrows the wrapped value without any runtime checks, even in debug mode or
+    /// with the `safe` feature, and without a guard.
+    ///
+    /// This is for hot paths where the checks would distort profiling; the borrow is invisible
+    /// to the checks of other borrows, so conflicts with it are never detected.
+    ///
+    /// # Safety
+    ///
+    /// The value must not be mutably borrowed for the lifetime of the returned reference.
+    #[inline]
+    pub unsafe fn borrow_unguarded(&self) -> &T {
+        unsafe { &*self.as_ptr() }
     }
-}
 
-pub struct MaybeAtomicRef<'b, T: ?Sized> {
-    #[cfg(any(debug_assertions, feature = "safe"))]
-    inner: atomic_refcell::AtomicRef<'b, T>,
-    #[cfg(not(any(debug_assertions, feature = "safe")))]
-    inner: &'b T,
-}
+    /// Mutably borrows the wrapped value without any runtime checks, even in debug mode or
+    /// with the `safe` feature, and without a guard.
+    ///
+    /// This is for hot paths where the checks would distort profiling; the borrow is invisible
+    /// to the checks of other borrows, so conflicts with it are never detected.
+    ///
+    /// # Safety
+    ///
+    /// The value must not be borrowed at all for the lifetime of the returned reference.
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn get_mut_unchecked(&self) -> &mut T {
+        unsafe { &mut *self.as_ptr() }
+    }
 
-impl<'b, T: ?Sized> MaybeAtomicRef<'b, T> {
-    /// Make a new `MaybeAtomicRef` for a component of the borrowed data.
+    /// Returns the underlying `AtomicRefCell`, if the runtime checks are enabled and backed by
+    /// one (not with the `blocking` or `side_table` features).
+    ///
+    /// Borrows of the returned cell and of this one are checked against each other, but the
+    /// former aren't accounted for in panic messages or by the `on_write` hook.
+    #[cfg(feature = "atomic_refcell")]
     #[inline]
-    pub fn map<U: ?Sized, F>(orig: MaybeAtomicRef<'b, T>, f: F) -> MaybeAtomicRef<'b, U>
-    where
-        F: FnOnce(&T) -> &U,
-    {
+    pub fn as_atomic_ref_cell(&self) -> Option<&atomic_refcell::AtomicRefCell<T>> {
+        #[cfg(all(
+            any(debug_assertions, feature = "safe"),
+            not(any(feature = "blocking", feature = "side_table"))
+        ))]
+        return Some(self.inner.as_atomic_ref_cell());
+        #[cfg(not(all(
+            any(debug_assertions, feature = "safe"),
+            not(any(feature = "blocking", feature = "side_table"))
+        )))]
+        None
+    }
+
+    /// Returns a raw pointer to the underlying data in this cell.
+    ///
+    /// External synchronization is needed to avoid data races when dereferencing
+    /// the pointer. Accesses through the pointer aren't checked; see `read_with`, `write_with`,
+    /// and `debug_assert_not_mutably_borrowed` for checked alternatives.
+    #[inline]
+    pub fn as_ptr(&self) -> *mut T {
         #[cfg(any(debug_assertions, feature = "safe"))]
-        return MaybeAtomicRef {
-            inner: atomic_refcell::AtomicRef::map(orig.inner, f),
-        };
+        return self.inner.as_ptr();
         #[cfg(not(any(debug_assertions, feature = "safe")))]
-        MaybeAtomicRef {
-            inner: f(orig.inner),
-        }
+        self.inner.get()
     }
 
-    /// Make a new `MaybeAtomicRef` for an optional component of the borrowed data.
+    /// Returns a raw pointer to the underlying data in this cell, along with the generation of
+    /// the cell, for `debug_validate`.
+    ///
+    /// In debug mode (or with the `safe` feature), the generation changes whenever a mutable
+    /// borrow of the cell is released, so that systems caching the pointer between frames can
+    /// detect that the data has since been mutated or moved. It is always 0 in release mode.
+    /// Generations are kept by address from the first call on, until the cell is dropped (a
+    /// cell moved in the meantime leaves its entry behind), and are unique, so a cell later
+    /// created at the same address doesn't validate its predecessor's pointers.
+    ///
+    /// ```
+    /// use maybe_atomic_refcell::MaybeAtomicRefCell;
+    ///
+    /// let cell = MaybeAtomicRefCell::new(1);
+    /// let (ptr, generation) = cell.as_versioned_ptr();
+    /// // Later, before dereferencing the cached pointer:
+    /// cell.debug_validate(ptr, generation);
+    /// ```
     #[inline]
-    pub fn filter_map<U: ?Sized, F>(
-        orig: MaybeAtomicRef<'b, T>,
-        f: F,
-    ) -> Option<MaybeAtomicRef<'b, U>>
-    where
-        F: FnOnce(&T) -> Option<&U>,
-    {
+    pub fn as_versioned_ptr(&self) -> (*mut T, u64) {
         #[cfg(any(debug_assertions, feature = "safe"))]
-        return atomic_refcell::AtomicRef::filter_map(orig.inner, f)
-            .map(|inner| MaybeAtomicRef { inner });
+        return (self.as_ptr(), generation::current(self.as_ptr()));
         #[cfg(not(any(debug_assertions, feature = "safe")))]
-        f(orig.inner).map(|inner| MaybeAtomicRef { inner })
+        (self.as_ptr(), 0)
     }
-}
-
-impl<'b, T: ?Sized> Deref for MaybeAtomicRef<'b, T> {
-    type Target = T;
 
-    fn deref(&self) -> &Self::Target {
+    /// Panics if `ptr` and `generation`, returned by `as_versioned_ptr`, are stale, in debug mode
+    /// (or with the `safe` feature): if the cell has moved, or has been mutably borrowed since.
+    /// Does nothing in release mode.
+    ///
+    /// Mutations through `get_mut`, `as_ptr`, and the other unchecked paths don't change the
+    /// generation.
+    #[inline]
+    #[track_caller]
+    pub fn debug_validate(&self, ptr: *const T, generation: u64) {
         #[cfg(any(debug_assertions, feature = "safe"))]
-        return self.inner.deref();
+        generation::validate(self.as_ptr(), ptr, generation);
         #[cfg(not(any(debug_assertions, feature = "safe")))]
-        self.inner
+        let _ = (ptr, generation);
     }
-}
 
-pub struct MaybeAtomicRefMut<'b, T: ?Sized> {
-    #[cfg(any(debug_assertions, feature = "safe"))]
-    inner: atomic_refcell::AtomicRefMut<'b, T>,
-    #[cfg(not(any(debug_assertions, feature = "safe")))]
-    inner: &'b mut T,
-}
+    /// Returns whether the cell has been mutably borrowed (or accessed through `get_mut`) since
+    /// its dirty flag was last reset, in any build mode, for skipping work when a value hasn't
+    /// changed.
+    ///
+    /// The flag is set once a mutable guard has been acquired, whether or not it is written
+    /// through. It is an `AtomicBool` in the cell, which starts out unchanged. Mutations through
+    /// `as_ptr` and the other unguarded paths aren't detected.
+    ///
+    /// ```
+    /// use maybe_atomic_refcell::MaybeAtomicRefCell;
+    ///
+    /// let cell = MaybeAtomicRefCell::new(1);
+    /// assert!(!cell.is_changed());
+    /// unsafe {
+    ///     *cell.borrow_mut() += 1;
+    /// }
+    /// assert!(cell.is_changed());
+    /// ```
+    #[cfg(feature = "change_detection")]
+    #[inline]
+    pub fn is_changed(&self) -> bool {
+        self.changed.load(std::sync::atomic::Ordering::Relaxed)
+    }
 
-impl<'b, T: ?Sized> MaybeAtomicRefMut<'b, T> {
-    /// Make a new `MaybeAtomicRef` for a component of the borrowed data.
+    /// Clears the dirty flag of the cell, returning whether it was set. See `is_changed`.
+    #[cfg(feature = "change_detection")]
     #[inline]
-    pub fn map<U: ?Sized, F>(orig: MaybeAtomicRefMut<'b, T>, f: F) -> MaybeAtomicRefMut<'b, U>
-    where
-        F: FnOnce(&mut T) -> &mut U,
-    {
-        #[cfg(any(debug_assertions, feature = "safe"))]
-        return MaybeAtomicRefMut {
-            inner: atomic_refcell::AtomicRefMut::map(orig.inner, f),
-        };
-        #[cfg(not(any(debug_assertions, feature = "safe")))]
-        MaybeAtomicRefMut {
-            inner: f(orig.inner),
-        }
+    pub fn reset_changed(&self) -> bool {
+        self.changed
+            .swap(false, std::sync::atomic::Ordering::Relaxed)
     }
 
-    /// Make a new `MaybeAtomicRef` for an optional component of the borrowed data.
+    /// Sets the dirty flag of the cell.
+    #[cfg(feature = "change_detection")]
     #[inline]
-    pub fn filter_map<U: ?Sized, F>(
-        orig: MaybeAtomicRefMut<'b, T>,
-        f: F,
-    ) -> Option<MaybeAtomicRefMut<'b, U>>
-    where
-        F: FnOnce(&mut T) -> Option<&mut U>,
-    {
-        #[cfg(any(debug_assertions, feature = "safe"))]
-        return atomic_refcell::AtomicRefMut::filter_map(orig.inner, f)
-            .map(|inner| MaybeAtomicRefMut { inner });
-        #[cfg(not(any(debug_assertions, feature = "safe")))]
-        f(orig.inner).map(|inner| MaybeAtomicRefMut { inner })
+    pub(crate) fn mark_changed(&self) {
+        self.changed
+            .store(true, std::sync::atomic::Ordering::Relaxed);
     }
-}
 
-impl<'b, T: ?Sized> Deref for MaybeAtomicRefMut<'b, T> {
-    type Target = T;
+    /// Returns a non-null pointer to the underlying data in this cell.
+    ///
+    /// External synchronization is needed to avoid data races when dereferencing
+    /// the pointer.
+    #[inline]
+    pub fn as_non_null(&self) -> NonNull<T> {
+        // SAFETY: The pointer is derived from a reference.
+        unsafe { NonNull::new_unchecked(self.as_ptr()) }
+    }
 
-    fn deref(&self) -> &Self::Target {
-        #[cfg(any(debug_assertions, feature = "safe"))]
-        return self.inner.deref();
-        #[cfg(not(any(debug_assertions, feature = "safe")))]
-        self.inner
+    /// Gets a raw pointer to the underlying data from a raw pointer to the cell, like
+    /// `UnsafeCell::raw_get`. In release mode, no reference to the cell is created.
+    ///
+    /// # Safety
+    ///
+    /// In debug mode (or with the `change_detection` feature), `this` is dereferenced, so it must
+    /// point to a valid `MaybeAtomicRefCell<T>` (the wrapped value may be uninitialized, e.g. if
+    /// `T` is `MaybeUninit<_>`). In release mode, there are no requirements.
+    #[inline]
+    pub unsafe fn raw_get(this: *const Self) -> *mut T {
+        #[cfg(any(debug_assertions, feature = "safe", feature = "change_detection"))]
+        return unsafe { (*this).as_ptr() };
+        // `MaybeAtomicRefCell<T>` is `repr(transparent)` over `UnsafeCell<T>`.
+        #[cfg(not(any(debug_assertions, feature = "safe", feature = "change_detection")))]
+        std::cell::UnsafeCell::raw_get(this as *const std::cell::UnsafeCell<T>)
     }
-}
 
-impl<'b, T: ?Sized> DerefMut for MaybeAtomicRefMut<'b, T> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
+    /// Returns a mutable reference to the wrapped value.
+    ///
+    /// No runtime checks take place (unless debug assertions are enabled)
+    /// because this call borrows `MaybeAtomicRefCell` mutably at compile-time.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        #[cfg(feature = "change_detection")]
+        {
+            *self.changed.get_mut() = true;
+        }
+        self.inner.get_mut()
+    }
+
+    /// Returns a mutable reference to the wrapped value, if `arc` is the only `Arc` (or `Weak`)
+    /// pointing to the cell, like `Arc::get_mut` followed by `get_mut`.
+    ///
+    /// No runtime checks take place because no other `Arc` can borrow the value meanwhile.
+    #[inline]
+    pub fn arc_get_mut(arc: &mut std::sync::Arc<MaybeAtomicRefCell<T>>) -> Option<&mut T> {
+        std::sync::Arc::get_mut(arc).map(MaybeAtomicRefCell::get_mut)
+    }
+
+    /// Mutably borrows several distinct cells at once. Performs runtime checks in debug mode,
+    /// including that no cell is passed twice, but not in release mode (hence `unsafe`).
+    ///
+    /// The cells are acquired in address order, regardless of the order they are passed in, so
+    /// that threads acquiring overlapping sets of cells agree on a single order.
+    ///
+    /// # Safety
+    ///
+    /// The cells must be distinct, and none of them may be borrowed at all for the lifetime of
+    /// the returned guards.
+    #[inline]
+    #[track_caller]
+    pub unsafe fn borrow_many_mut<'b, const N: usize>(
+        cells: [&'b Self; N],
+    ) -> [MaybeAtomicRefMut<'b, T>; N] {
+        #[cfg(any(debug_assertions, feature = "safe"))]
+        {
+            for (i, a) in cells.iter().enumerate() {
+                for (j, b) in cells[..i].iter().enumerate() {
+                    if std::ptr::eq(a.as_ptr() as *const (), b.as_ptr() as *const ()) {
+                        fixed::violation(format_args!(
+                            "cells {} and {} are the same MaybeAtomicRefCell",
+                            j, i
+                        ));
+                    }
+                }
+            }
+        }
+        #[cfg(any(debug_assertions, feature = "safe"))]
+        let guards = {
+            let mut order: [usize; N] = std::array::from_fn(|i| i);
+            order.sort_unstable_by_key(|&i| reentrancy::address(cells[i]));
+            let mut guards: [Option<MaybeAtomicRefMut<'b, T>>; N] = std::array::from_fn(|_| None);
+            for i in order {
+                let cell = &cells[i].inner;
+                guards[i] = Some(fixed::acquire_mut(cell).unwrap_or_else(|| {
+                    fixed::violation(format_args!(
+                        "cell {} is already borrowed {}",
+                        i,
+                        reentrancy::culprit(reentrancy::id(cell))
+                    ))
+                }));
+                #[cfg(feature = "change_detection")]
+                cells[i].mark_changed();
+            }
+            guards
+        };
         #[cfg(any(debug_assertions, feature = "safe"))]
-        return self.inner.deref_mut();
+        return guards.map(Option::unwrap);
         #[cfg(not(any(debug_assertions, feature = "safe")))]
-        self.inner
+        #[allow(unused_unsafe)]
+        cells.map(|cell| unsafe { cell.borrow_mut() })
     }
-}
 
-impl<'b, T: ?Sized + Debug + 'b> Debug for MaybeAtomicRef<'b, T> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.inner.fmt(f)
+    /// Mutably borrows two distinct cells at once. See `borrow_many_mut`.
+    ///
+    /// # Safety
+    ///
+    /// See `borrow_many_mut`.
+    #[inline]
+    #[track_caller]
+    pub unsafe fn borrow_mut_both<'b>(
+        a: &'b Self,
+        b: &'b Self,
+    ) -> (MaybeAtomicRefMut<'b, T>, MaybeAtomicRefMut<'b, T>) {
+        let [a, b] = unsafe { Self::borrow_many_mut([a, b]) };
+        (a, b)
     }
 }
 
-impl<'b, T: ?Sized + Debug + 'b> Debug for MaybeAtomicRefMut<'b, T> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.inner.fmt(f)
+impl<T: Clone> MaybeAtomicRefCell<T> {
+    /// Immutably borrows the wrapped value just long enough to clone it. Performs runtime checks
+    /// in debug mode, but not in release mode (hence `unsafe`).
+    ///
+    /// # Safety
+    ///
+    /// The value must not be mutably borrowed.
+    #[inline]
+    #[track_caller]
+    pub unsafe fn get_cloned(&self) -> T {
+        unsafe { self.borrow() }.clone()
     }
 }
 
-impl<T: ?Sized + Debug> Debug for MaybeAtomicRefCell<T> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "MaybeAtomicRefCell {{ ... }}")
+#[cfg(feature = "bytemuck")]
+impl<T: bytemuck::Zeroable> MaybeAtomicRefCell<T> {
+    /// Creates a new `MaybeAtomicRefCell` containing an all-zeros value.
+    #[inline]
+    pub fn zeroed() -> MaybeAtomicRefCell<T> {
+        MaybeAtomicRefCell::new(T::zeroed())
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::MaybeAtomicRefCell;
+impl<T> MaybeAtomicRefCell<Option<T>> {
+    /// Immutably borrows the contents of the wrapped `Option`, if any. Performs runtime checks
+    /// in debug mode, but not in release mode (hence `unsafe`).
+    ///
+    /// # Safety
+    ///
+    /// See `borrow`.
+    #[inline]
+    #[track_caller]
+    pub unsafe fn borrow_some(&self) -> Option<MaybeAtomicRef<'_, T>> {
+        MaybeAtomicRef::transpose(unsafe { self.borrow() })
+    }
 
-    #[test]
-    fn it_works() {
-        let cell = MaybeAtomicRefCell::new(5);
+    /// Mutably borrows the contents of the wrapped `Option`, if any. Performs runtime checks in
+    /// debug mode, but not in release mode (hence `unsafe`).
+    ///
+    /// # Safety
+    ///
+    /// See `borrow_mut`.
+    #[inline]
+    #[track_caller]
+    pub unsafe fn borrow_some_mut(&self) -> Option<MaybeAtomicRefMut<'_, T>> {
+        MaybeAtomicRefMut::transpose(unsafe { self.borrow_mut() })
+    }
+}
 
-        unsafe {
-            {
-                assert_eq!(*cell.borrow(), 5);
-                let _borrow1 = cell.borrow();
-                let _borrow2 = cell.borrow();
+impl<T> MaybeAtomicRefCell<MaybeUninit<T>> {
+    /// Creates a new `MaybeAtomicRefCell` containing an uninitialized value.
+    #[inline]
+    pub const fn uninit() -> MaybeAtomicRefCell<MaybeUninit<T>> {
+        MaybeAtomicRefCell::new(MaybeUninit::uninit())
+    }
+
+    /// Mutably borrows the wrapped value just long enough to initialize it with `value`.
+    /// Performs runtime checks in debug mode, but not in release mode (hence `unsafe`).
+    ///
+    /// Like `MaybeUninit::write`, this overwrites any previous value without dropping it.
+    ///
+    /// # Safety
+    ///
+    /// The value must not be borrowed at all.
+    #[inline]
+    #[track_caller]
+    pub unsafe fn write(&self, value: T) {
+        unsafe { self.borrow_mut() }.write(value);
+    }
+
+    /// Converts to `MaybeAtomicRefCell<T>`.
+    ///
+    /// # Safety
+    ///
+    /// The wrapped value must be initialized, as in `MaybeUninit::assume_init`.
+    #[inline]
+    pub unsafe fn assume_init(self) -> MaybeAtomicRefCell<T> {
+        MaybeAtomicRefCell::new(unsafe { self.into_inner().assume_init() })
+    }
+}
+
+macro_rules! impl_downcast {
+    ($($ty:ty),+) => {$(
+        impl MaybeAtomicRefCell<$ty> {
+            /// Immutably borrows the wrapped value if it is a `U`. Performs runtime checks in
+            /// debug mode, but not in release mode (hence `unsafe`).
+            ///
+            /// # Safety
+            ///
+            /// See `borrow`.
+            #[inline]
+            #[track_caller]
+            pub unsafe fn borrow_downcast<U: Any>(&self) -> Option<MaybeAtomicRef<'_, U>> {
+                MaybeAtomicRef::filter_map(unsafe { self.borrow() }, |value| {
+                    value.downcast_ref::<U>()
+                })
+            }
+
+            /// Mutably borrows the wrapped value if it is a `U`. Performs runtime checks in
+            /// debug mode, but not in release mode (hence `unsafe`).
+            ///
+            /// # Safety
+            ///
+            /// See `borrow_mut`.
+            #[inline]
+            #[track_caller]
+            pub unsafe fn borrow_mut_downcast<U: Any>(&self) -> Option<MaybeAtomicRefMut<'_, U>> {
+                MaybeAtomicRefMut::filter_map(unsafe { self.borrow_mut() }, |value| {
+                    value.downcast_mut::<U>()
+                })
+            }
+        }
+    )+};
+}
+
+impl_downcast!(
+    dyn Any,
+    dyn Any + Send,
+    dyn Any + Send + Sync,
+    Box<dyn Any>,
+    Box<dyn Any + Send>,
+    Box<dyn Any + Send + Sync>
+);
+
+impl<T, const N: usize> MaybeAtomicRefCell<[T; N]> {
+    /// Mutably borrows the array, returning a guard for each element, so that they can be
+    /// handed out separately.
+    ///
+    /// The array stays mutably borrowed until all of the guards are dropped.
+    ///
+    /// # Safety
+    ///
+    /// See `borrow_mut`.
+    #[inline]
+    #[track_caller]
+    pub unsafe fn borrow_mut_each(&self) -> [MaybeAtomicRefMut<'_, T>; N] {
+        let mut each = split_each(MaybeAtomicRefMut::map(
+            unsafe { self.borrow_mut() },
+            |array| array.as_mut_slice(),
+        ));
+        std::array::from_fn(|_| each.next().unwrap())
+    }
+}
+
+impl<T> MaybeAtomicRefCell<[T]> {
+    /// Immutably borrows the slice, returning an iterator of guards for its elements.
+    ///
+    /// The slice stays immutably borrowed until the iterator and all of its guards are dropped.
+    ///
+    /// # Safety
+    ///
+    /// See `borrow`.
+    #[inline]
+    #[track_caller]
+    pub unsafe fn borrow_iter(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = MaybeAtomicRef<'_, T>> + ExactSizeIterator {
+        let guard = unsafe { self.borrow() };
+        (0..guard.len()).map(move |i| MaybeAtomicRef::map(MaybeAtomicRef::clone(&guard), |s| &s[i]))
+    }
+
+    /// Mutably borrows the slice, returning an iterator of guards for its elements.
+    ///
+    /// The slice stays mutably borrowed until the iterator and all of its guards are dropped.
+    ///
+    /// # Safety
+    ///
+    /// See `borrow_mut`.
+    #[inline]
+    #[track_caller]
+    pub unsafe fn borrow_iter_mut(&self) -> impl Iterator<Item = MaybeAtomicRefMut<'_, T>> {
+        split_each(unsafe { self.borrow_mut() })
+    }
+}
+
+impl<T> MaybeAtomicRefCell<Vec<T>> {
+    /// Immutably borrows the `Vec`, returning an iterator of guards for its elements.
+    ///
+    /// # Safety
+    ///
+    /// See `borrow`.
+    #[inline]
+    #[track_caller]
+    pub unsafe fn borrow_iter(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = MaybeAtomicRef<'_, T>> + ExactSizeIterator {
+        let guard = MaybeAtomicRef::map(unsafe { self.borrow() }, Vec::as_slice);
+        (0..guard.len()).map(move |i| MaybeAtomicRef::map(MaybeAtomicRef::clone(&guard), |s| &s[i]))
+    }
+
+    /// Mutably borrows the `Vec`, returning an iterator of guards for its elements.
+    ///
+    /// # Safety
+    ///
+    /// See `borrow_mut`.
+    #[inline]
+    #[track_caller]
+    pub unsafe fn borrow_iter_mut(&self) -> impl Iterator<Item = MaybeAtomicRefMut<'_, T>> {
+        split_each(MaybeAtomicRefMut::map(
+            unsafe { self.borrow_mut() },
+            Vec::as_mut_slice,
+        ))
+    }
+}
+
+unsafe impl<T: ?Sized + Send> Send for MaybeAtomicRefCell<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for MaybeAtomicRefCell<T> {}
+
+impl<T: Default> Default for MaybeAtomicRefCell<T> {
+    #[inline]
+    fn default() -> MaybeAtomicRefCell<T> {
+        MaybeAtomicRefCell::new(Default::default())
+    }
+}
+
+#[cfg(feature = "atomic_refcell")]
+impl<T> From<atomic_refcell::AtomicRefCell<T>> for MaybeAtomicRefCell<T> {
+    fn from(cell: atomic_refcell::AtomicRefCell<T>) -> MaybeAtomicRefCell<T> {
+        #[cfg(any(debug_assertions, feature = "safe"))]
+        return MaybeAtomicRefCell {
+            #[cfg(feature = "change_detection")]
+            changed: std::sync::atomic::AtomicBool::new(false),
+            inner: backend::BorrowCell::from_atomic_ref_cell(cell),
+        };
+        #[cfg(not(any(debug_assertions, feature = "safe")))]
+        MaybeAtomicRefCell::new(cell.into_inner())
+    }
+}
+
+impl<T> From<std::cell::RefCell<T>> for MaybeAtomicRefCell<T> {
+    fn from(cell: std::cell::RefCell<T>) -> MaybeAtomicRefCell<T> {
+        MaybeAtomicRefCell::new(cell.into_inner())
+    }
+}
+
+impl<T: Clone> Clone for MaybeAtomicRefCell<T> {
+    #[inline]
+    fn clone(&self) -> MaybeAtomicRefCell<T> {
+        // SAFETY: Upheld by the caller of `borrow_mut`.
+        MaybeAtomicRefCell::new(unsafe { self.borrow() }.clone())
+    }
+}
+
+impl<T: ?Sized + PartialEq> PartialEq for MaybeAtomicRefCell<T> {
+    #[inline]
+    fn eq(&self, other: &MaybeAtomicRefCell<T>) -> bool {
+        // SAFETY: Upheld by the callers of `borrow_mut`.
+        unsafe { *self.borrow() == *other.borrow() }
+    }
+}
+
+impl<T: ?Sized + Eq> Eq for MaybeAtomicRefCell<T> {}
+
+impl<T: ?Sized + PartialOrd> PartialOrd for MaybeAtomicRefCell<T> {
+    #[inline]
+    fn partial_cmp(&self, other: &MaybeAtomicRefCell<T>) -> Option<Ordering> {
+        // SAFETY: Upheld by the callers of `borrow_mut`.
+        unsafe { (*self.borrow()).partial_cmp(&*other.borrow()) }
+    }
+}
+
+impl<T: ?Sized + Ord> Ord for MaybeAtomicRefCell<T> {
+    #[inline]
+    fn cmp(&self, other: &MaybeAtomicRefCell<T>) -> Ordering {
+        // SAFETY: Upheld by the callers of `borrow_mut`.
+        unsafe { (*self.borrow()).cmp(&*other.borrow()) }
+    }
+}
+
+impl<T: ?Sized + Hash> Hash for MaybeAtomicRefCell<T> {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // SAFETY: Upheld by the caller of `borrow_mut`.
+        unsafe { self.borrow() }.hash(state)
+    }
+}
+
+impl<T> From<T> for MaybeAtomicRefCell<T> {
+    fn from(t: T) -> MaybeAtomicRefCell<T> {
+        MaybeAtomicRefCell::new(t)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for MaybeAtomicRefCell<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        T::deserialize(deserializer).map(Self::from)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: ?Sized + serde::Serialize> serde::Serialize for MaybeAtomicRefCell<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        #[cfg(any(debug_assertions, feature = "safe"))]
+        return match fixed::try_borrow(&self.inner) {
+            Some(value) => value.serialize(serializer),
+            None => Err(serde::ser::Error::custom("already mutably borrowed")),
+        };
+        // SAFETY: Upheld by the caller of `borrow_mut`.
+        #[cfg(not(any(debug_assertions, feature = "safe")))]
+        unsafe { &*self.inner.get() }.serialize(serializer)
+    }
+}
+
+/// An archived `MaybeAtomicRefCell`, with the same layout as the archived value.
+#[cfg(feature = "rkyv")]
+#[repr(transparent)]
+pub struct ArchivedMaybeAtomicRefCell<T>(T);
+
+#[cfg(feature = "rkyv")]
+impl<T> Deref for ArchivedMaybeAtomicRefCell<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+// SAFETY: `ArchivedMaybeAtomicRefCell<T>` is `repr(transparent)` over `T`.
+#[cfg(feature = "rkyv")]
+unsafe impl<T: rkyv::Portable> rkyv::Portable for ArchivedMaybeAtomicRefCell<T> {}
+
+// SAFETY: `ArchivedMaybeAtomicRefCell<T>` is `repr(transparent)` over `T`.
+#[cfg(feature = "rkyv")]
+unsafe impl<T, C> rkyv::bytecheck::CheckBytes<C> for ArchivedMaybeAtomicRefCell<T>
+where
+    T: rkyv::bytecheck::CheckBytes<C>,
+    C: rkyv::rancor::Fallible + ?Sized,
+{
+    #[inline]
+    unsafe fn check_bytes(value: *const Self, context: &mut C) -> Result<(), C::Error> {
+        T::check_bytes(value.cast(), context)
+    }
+}
+
+#[cfg(feature = "rkyv")]
+impl<T: rkyv::Archive> rkyv::Archive for MaybeAtomicRefCell<T> {
+    type Archived = ArchivedMaybeAtomicRefCell<T::Archived>;
+    type Resolver = T::Resolver;
+
+    fn resolve(&self, resolver: Self::Resolver, out: rkyv::Place<Self::Archived>) {
+        // SAFETY: `ArchivedMaybeAtomicRefCell<T::Archived>` is `repr(transparent)` over
+        // `T::Archived`.
+        let out = unsafe { out.cast_unchecked::<T::Archived>() };
+        // SAFETY: Upheld by the caller of `borrow_mut`.
+        unsafe { self.borrow() }.resolve(resolver, out)
+    }
+}
+
+#[cfg(feature = "rkyv")]
+impl<T, S> rkyv::Serialize<S> for MaybeAtomicRefCell<T>
+where
+    T: rkyv::Serialize<S>,
+    S: rkyv::rancor::Fallible + ?Sized,
+{
+    fn serialize(&self, serializer: &mut S) -> Result<Self::Resolver, S::Error> {
+        // SAFETY: Upheld by the caller of `borrow_mut`.
+        unsafe { self.borrow() }.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "rkyv")]
+impl<T, D> rkyv::Deserialize<MaybeAtomicRefCell<T>, D> for ArchivedMaybeAtomicRefCell<T::Archived>
+where
+    T: rkyv::Archive,
+    T::Archived: rkyv::Deserialize<T, D>,
+    D: rkyv::rancor::Fallible + ?Sized,
+{
+    fn deserialize(&self, deserializer: &mut D) -> Result<MaybeAtomicRefCell<T>, D::Error> {
+        self.0
+            .deserialize(deserializer)
+            .map(MaybeAtomicRefCell::new)
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a, T: arbitrary::Arbitrary<'a>> arbitrary::Arbitrary<'a> for MaybeAtomicRefCell<T> {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        T::arbitrary(u).map(Self::new)
+    }
+
+    fn arbitrary_take_rest(u: arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        T::arbitrary_take_rest(u).map(Self::new)
+    }
+
+    #[inline]
+    fn size_hint(depth: usize) -> (usize, Option<usize>) {
+        T::size_hint(depth)
+    }
+
+    #[inline]
+    fn try_size_hint(
+        depth: usize,
+    ) -> Result<(usize, Option<usize>), arbitrary::MaxRecursionReached> {
+        T::try_size_hint(depth)
+    }
+}
+
+#[cfg(feature = "proptest")]
+impl<T: proptest::arbitrary::Arbitrary> proptest::arbitrary::Arbitrary for MaybeAtomicRefCell<T> {
+    type Parameters = T::Parameters;
+    type Strategy = proptest::strategy::MapInto<T::Strategy, Self>;
+
+    fn arbitrary_with(args: Self::Parameters) -> Self::Strategy {
+        Self::strategy(proptest::arbitrary::any_with::<T>(args))
+    }
+}
+
+#[cfg(feature = "proptest")]
+impl<T: Debug> MaybeAtomicRefCell<T> {
+    /// Maps a strategy for `T` into a strategy for `MaybeAtomicRefCell<T>`.
+    #[inline]
+    pub fn strategy<S>(strategy: S) -> proptest::strategy::MapInto<S, Self>
+    where
+        S: proptest::strategy::Strategy<Value = T>,
+    {
+        proptest::strategy::Strategy::prop_map_into(strategy)
+    }
+}
+
+/// Parallel iteration over a slice of `MaybeAtomicRefCell`s, borrowing each element.
+#[cfg(feature = "rayon")]
+pub trait ParallelBorrowExt<T> {
+    /// Immutably borrows each cell, yielding the guards across the thread pool.
+    ///
+    /// # Safety
+    ///
+    /// No cell may be mutably borrowed for the lifetime of the yielded guards.
+    unsafe fn par_iter_borrow<'b>(
+        &'b self,
+    ) -> impl rayon::iter::IndexedParallelIterator<Item = MaybeAtomicRef<'b, T>>
+    where
+        T: 'b;
+
+    /// Mutably borrows each cell, yielding the guards across the thread pool.
+    ///
+    /// # Safety
+    ///
+    /// No cell may be borrowed elsewhere for the lifetime of the yielded guards.
+    unsafe fn par_iter_borrow_mut<'b>(
+        &'b self,
+    ) -> impl rayon::iter::IndexedParallelIterator<Item = MaybeAtomicRefMut<'b, T>>
+    where
+        T: 'b;
+}
+
+#[cfg(feature = "rayon")]
+impl<T: Send + Sync> ParallelBorrowExt<T> for [MaybeAtomicRefCell<T>] {
+    #[inline]
+    unsafe fn par_iter_borrow<'b>(
+        &'b self,
+    ) -> impl rayon::iter::IndexedParallelIterator<Item = MaybeAtomicRef<'b, T>>
+    where
+        T: 'b,
+    {
+        use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+        // SAFETY: Upheld by the caller.
+        self.par_iter().map(|cell| unsafe { cell.borrow() })
+    }
+
+    #[inline]
+    unsafe fn par_iter_borrow_mut<'b>(
+        &'b self,
+    ) -> impl rayon::iter::IndexedParallelIterator<Item = MaybeAtomicRefMut<'b, T>>
+    where
+        T: 'b,
+    {
+        use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+        // SAFETY: Upheld by the caller. Each element is a distinct cell.
+        self.par_iter().map(|cell| unsafe { cell.borrow_mut() })
+    }
+}
+
+/// Best-effort iteration over a slice (or `Vec`) of `MaybeAtomicRefCell`s, borrowing each element
+/// that isn't already borrowed, for debug tooling such as inspectors that walk cells while other
+/// code may be using some of them.
+///
+/// In debug mode, cells with conflicting borrows yield `None` instead of panicking (or waiting,
+/// with the `blocking` backend), so `flatten` skips them and `enumerate` reports them. In release
+/// mode, every cell yields `Some` (hence `unsafe`).
+///
+/// ```
+/// use maybe_atomic_refcell::{MaybeAtomicRefCell, TryBorrowExt};
+///
+/// let cells: Vec<_> = (0..3).map(MaybeAtomicRefCell::new).collect();
+/// unsafe {
+///     let sum: i32 = cells.iter_try_borrow().flatten().map(|value| *value).sum();
+///     assert_eq!(sum, 3);
+/// }
+/// ```
+pub trait TryBorrowExt<T> {
+    /// Immutably borrows each cell that isn't mutably borrowed, yielding `None` for the rest (in
+    /// debug mode).
+    ///
+    /// # Safety
+    ///
+    /// In release mode, no cell may be mutably borrowed for the lifetime of the yielded guards.
+    unsafe fn iter_try_borrow<'b>(
+        &'b self,
+    ) -> impl ExactSizeIterator<Item = Option<MaybeAtomicRef<'b, T>>>
+    where
+        T: 'b;
+
+    /// Mutably borrows each cell that isn't borrowed at all, yielding `None` for the rest (in
+    /// debug mode).
+    ///
+    /// # Safety
+    ///
+    /// In release mode, no cell may be borrowed elsewhere for the lifetime of the yielded guards.
+    unsafe fn iter_try_borrow_mut<'b>(
+        &'b self,
+    ) -> impl ExactSizeIterator<Item = Option<MaybeAtomicRefMut<'b, T>>>
+    where
+        T: 'b;
+}
+
+impl<T> TryBorrowExt<T> for [MaybeAtomicRefCell<T>] {
+    #[inline]
+    unsafe fn iter_try_borrow<'b>(
+        &'b self,
+    ) -> impl ExactSizeIterator<Item = Option<MaybeAtomicRef<'b, T>>>
+    where
+        T: 'b,
+    {
+        self.iter().map(|cell| {
+            #[cfg(any(debug_assertions, feature = "safe"))]
+            return fixed::try_borrow(&cell.inner);
+            // SAFETY: Upheld by the caller.
+            #[cfg(not(any(debug_assertions, feature = "safe")))]
+            Some(unsafe { cell.borrow() })
+        })
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn iter_try_borrow_mut<'b>(
+        &'b self,
+    ) -> impl ExactSizeIterator<Item = Option<MaybeAtomicRefMut<'b, T>>>
+    where
+        T: 'b,
+    {
+        // The closure can't track its caller, so the location is taken here.
+        #[cfg(any(debug_assertions, feature = "safe"))]
+        let location = std::panic::Location::caller();
+        self.iter().map(move |cell| {
+            #[cfg(any(debug_assertions, feature = "safe"))]
+            let guard = fixed::try_borrow_mut(&cell.inner, location);
+            // SAFETY: Upheld by the caller. Each element is a distinct cell.
+            #[cfg(not(any(debug_assertions, feature = "safe")))]
+            let guard = Some(unsafe { fixed::borrow_mut_unchecked(cell.inner.get()) });
+            #[cfg(feature = "change_detection")]
+            if guard.is_some() {
+                cell.mark_changed();
+            }
+            guard
+        })
+    }
+}
+
+/// The borrow held by a `MaybeAtomicRef` from a `MaybeAtomicRefCell`, which depends on whether
+/// runtime checks are enabled.
+#[cfg(any(debug_assertions, feature = "safe"))]
+pub type DefaultBorrow<'b> = CheckedBorrow<'b>;
+/// The borrow held by a `MaybeAtomicRef` from a `MaybeAtomicRefCell`, which depends on whether
+/// runtime checks are enabled.
+#[cfg(not(any(debug_assertions, feature = "safe")))]
+pub type DefaultBorrow<'b> = UncheckedBorrow;
+
+/// The borrow held by a `MaybeAtomicRefMut` from a `MaybeAtomicRefCell`, which depends on
+/// whether runtime checks are enabled.
+#[cfg(any(debug_assertions, feature = "safe"))]
+pub type DefaultBorrowMut<'b> = CheckedBorrowMut<'b>;
+/// The borrow held by a `MaybeAtomicRefMut` from a `MaybeAtomicRefCell`, which depends on
+/// whether runtime checks are enabled.
+#[cfg(not(any(debug_assertions, feature = "safe")))]
+pub type DefaultBorrowMut<'b> = UncheckedBorrow;
+
+/// A shorter name for `MaybeAtomicRef`.
+pub type MaybeRef<'b, T, B = DefaultBorrow<'b>> = MaybeAtomicRef<'b, T, B>;
+
+/// A shorter name for `MaybeAtomicRefMut`.
+pub type MaybeRefMut<'b, T, B = DefaultBorrowMut<'b>> = MaybeAtomicRefMut<'b, T, B>;
+
+/// A wrapper type for an immutably borrowed value from a `MaybeAtomicRefCell<T>`.
+///
+/// `B` is the borrow being held, which is only not the default for guards from a
+/// `CheckedAtomicRefCell` or an `UncheckedCell`.
+#[cfg_attr(
+    feature = "nightly",
+    must_not_suspend = "holding a guard across a suspend point can alias a borrow from another \
+    task, which is undefined behavior in release mode"
+)]
+pub struct MaybeAtomicRef<'b, T: ?Sized, B = DefaultBorrow<'b>> {
+    value: NonNull<T>,
+    borrow: B,
+    marker: PhantomData<&'b T>,
+}
+
+impl<'b, T: ?Sized, B> MaybeAtomicRef<'b, T, B> {
+    /// Make a new `MaybeAtomicRef` for a component of the borrowed data.
+    #[inline]
+    pub fn map<U: ?Sized, F>(orig: MaybeAtomicRef<'b, T, B>, f: F) -> MaybeAtomicRef<'b, U, B>
+    where
+        F: FnOnce(&T) -> &U,
+    {
+        MaybeAtomicRef {
+            value: NonNull::from(f(unsafe { orig.value.as_ref() })),
+            borrow: orig.borrow,
+            marker: PhantomData,
+        }
+    }
+
+    /// Make a new `MaybeAtomicRef` for an optional component of the borrowed data.
+    #[inline]
+    pub fn filter_map<U: ?Sized, F>(
+        orig: MaybeAtomicRef<'b, T, B>,
+        f: F,
+    ) -> Option<MaybeAtomicRef<'b, U, B>>
+    where
+        F: FnOnce(&T) -> Option<&U>,
+    {
+        f(unsafe { orig.value.as_ref() }).map(|value| MaybeAtomicRef {
+            value: NonNull::from(value),
+            borrow: orig.borrow,
+            marker: PhantomData,
+        })
+    }
+
+    /// Make a new `MaybeAtomicRef` for a fallible component of the borrowed data. On error, the
+    /// original guard is returned alongside it.
+    #[inline]
+    pub fn try_map<U: ?Sized, E, F>(
+        orig: MaybeAtomicRef<'b, T, B>,
+        f: F,
+    ) -> Result<MaybeAtomicRef<'b, U, B>, (MaybeAtomicRef<'b, T, B>, E)>
+    where
+        F: FnOnce(&T) -> Result<&U, E>,
+    {
+        match f(unsafe { orig.value.as_ref() }) {
+            Ok(value) => Ok(MaybeAtomicRef {
+                value: NonNull::from(value),
+                borrow: orig.borrow,
+                marker: PhantomData,
+            }),
+            Err(e) => Err((orig, e)),
+        }
+    }
+}
+
+impl<'b, T: ?Sized, B: Clone> MaybeAtomicRef<'b, T, B> {
+    /// Copies a `MaybeAtomicRef`, taking another immutable borrow of the same cell.
+    ///
+    /// This is an associated function, so that it doesn't shadow `Clone::clone` on the value.
+    #[inline]
+    #[allow(clippy::should_implement_trait)]
+    pub fn clone(orig: &MaybeAtomicRef<'b, T, B>) -> MaybeAtomicRef<'b, T, B> {
+        MaybeAtomicRef {
+            value: orig.value,
+            borrow: orig.borrow.clone(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Splits a `MaybeAtomicRef` into multiple `MaybeAtomicRef`s for different components of
+    /// the borrowed data.
+    #[inline]
+    pub fn map_split<U: ?Sized, V: ?Sized, F>(
+        orig: MaybeAtomicRef<'b, T, B>,
+        f: F,
+    ) -> (MaybeAtomicRef<'b, U, B>, MaybeAtomicRef<'b, V, B>)
+    where
+        F: FnOnce(&T) -> (&U, &V),
+    {
+        let (u, v) = f(unsafe { orig.value.as_ref() });
+        (
+            MaybeAtomicRef {
+                value: NonNull::from(u),
+                borrow: orig.borrow.clone(),
+                marker: PhantomData,
+            },
+            MaybeAtomicRef {
+                value: NonNull::from(v),
+                borrow: orig.borrow,
+                marker: PhantomData,
+            },
+        )
+    }
+}
+
+impl<'b, T, B> MaybeAtomicRef<'b, Option<T>, B> {
+    /// Make a new `MaybeAtomicRef` for the contents of the borrowed `Option`, if any.
+    #[inline]
+    pub fn transpose(orig: MaybeAtomicRef<'b, Option<T>, B>) -> Option<MaybeAtomicRef<'b, T, B>> {
+        MaybeAtomicRef::filter_map(orig, Option::as_ref)
+    }
+}
+
+impl<'b, T: ?Sized, B> Deref for MaybeAtomicRef<'b, T, B> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        unsafe { self.value.as_ref() }
+    }
+}
+
+/// A wrapper type for a mutably borrowed value from a `MaybeAtomicRefCell<T>`.
+///
+/// `B` is the borrow being held, which is only not the default for guards from a
+/// `CheckedAtomicRefCell` or an `UncheckedCell`.
+#[cfg_attr(
+    feature = "nightly",
+    must_not_suspend = "holding a guard across a suspend point can alias a borrow from another \
+    task, which is undefined behavior in release mode"
+)]
+pub struct MaybeAtomicRefMut<'b, T: ?Sized, B = DefaultBorrowMut<'b>> {
+    value: NonNull<T>,
+    borrow: B,
+    marker: PhantomData<&'b mut T>,
+}
+
+impl<'b, T: ?Sized, B> MaybeAtomicRefMut<'b, T, B> {
+    /// Make a new `MaybeAtomicRef` for a component of the borrowed data.
+    #[inline]
+    pub fn map<U: ?Sized, F>(orig: MaybeAtomicRefMut<'b, T, B>, f: F) -> MaybeAtomicRefMut<'b, U, B>
+    where
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        MaybeAtomicRefMut {
+            value: NonNull::from(f(unsafe { &mut *orig.value.as_ptr() })),
+            borrow: orig.borrow,
+            marker: PhantomData,
+        }
+    }
+
+    /// Make a new `MaybeAtomicRef` for an optional component of the borrowed data.
+    #[inline]
+    pub fn filter_map<U: ?Sized, F>(
+        orig: MaybeAtomicRefMut<'b, T, B>,
+        f: F,
+    ) -> Option<MaybeAtomicRefMut<'b, U, B>>
+    where
+        F: FnOnce(&mut T) -> Option<&mut U>,
+    {
+        f(unsafe { &mut *orig.value.as_ptr() }).map(|value| MaybeAtomicRefMut {
+            value: NonNull::from(value),
+            borrow: orig.borrow,
+            marker: PhantomData,
+        })
+    }
+
+    /// Make a new `MaybeAtomicRefMut` for a fallible component of the borrowed data. On error,
+    /// the original guard is returned alongside it.
+    #[inline]
+    pub fn try_map<U: ?Sized, E, F>(
+        orig: MaybeAtomicRefMut<'b, T, B>,
+        f: F,
+    ) -> Result<MaybeAtomicRefMut<'b, U, B>, (MaybeAtomicRefMut<'b, T, B>, E)>
+    where
+        F: FnOnce(&mut T) -> Result<&mut U, E>,
+    {
+        match f(unsafe { &mut *orig.value.as_ptr() }) {
+            Ok(value) => Ok(MaybeAtomicRefMut {
+                value: NonNull::from(value),
+                borrow: orig.borrow,
+                marker: PhantomData,
+            }),
+            Err(e) => Err((orig, e)),
+        }
+    }
+}
+
+impl<'b, T: ?Sized, B: SplitBorrow> MaybeAtomicRefMut<'b, T, B> {
+    /// Splits a `MaybeAtomicRefMut` into multiple `MaybeAtomicRefMut`s for different components
+    /// of the borrowed data.
+    ///
+    /// The cell stays mutably borrowed until all of them are dropped.
+    #[inline]
+    pub fn map_split<U: ?Sized, V: ?Sized, F>(
+        orig: MaybeAtomicRefMut<'b, T, B>,
+        f: F,
+    ) -> (MaybeAtomicRefMut<'b, U, B>, MaybeAtomicRefMut<'b, V, B>)
+    where
+        F: FnOnce(&mut T) -> (&mut U, &mut V),
+    {
+        let (u, v) = f(unsafe { &mut *orig.value.as_ptr() });
+        let (borrow_u, borrow_v) = orig.borrow.split();
+        (
+            MaybeAtomicRefMut {
+                value: NonNull::from(u),
+                borrow: borrow_u,
+                marker: PhantomData,
+            },
+            MaybeAtomicRefMut {
+                value: NonNull::from(v),
+                borrow: borrow_v,
+                marker: PhantomData,
+            },
+        )
+    }
+}
+
+impl<'b, T, B: SplitBorrow> MaybeAtomicRefMut<'b, [T], B> {
+    /// Splits a `MaybeAtomicRefMut` for a slice into two at `mid`, like `<[T]>::split_at_mut`.
+    ///
+    /// The cell stays mutably borrowed until both halves are dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mid > len`.
+    #[inline]
+    pub fn split_at_mut(
+        orig: MaybeAtomicRefMut<'b, [T], B>,
+        mid: usize,
+    ) -> (MaybeAtomicRefMut<'b, [T], B>, MaybeAtomicRefMut<'b, [T], B>) {
+        MaybeAtomicRefMut::map_split(orig, |slice| slice.split_at_mut(mid))
+    }
+}
+
+/// Splits a guard for a slice into guards for each of its elements.
+fn split_each<'b, T, B: SplitBorrow>(
+    guard: MaybeAtomicRefMut<'b, [T], B>,
+) -> impl Iterator<Item = MaybeAtomicRefMut<'b, T, B>> {
+    let mut rest = Some(guard);
+    std::iter::from_fn(move || {
+        let guard = MaybeAtomicRefMut::filter_map(rest.take()?, |s| (!s.is_empty()).then_some(s))?;
+        let (first, tail) = MaybeAtomicRefMut::map_split(guard, |s| s.split_first_mut().unwrap());
+        rest = Some(tail);
+        Some(first)
+    })
+}
+
+impl<'b, T, B> MaybeAtomicRefMut<'b, Option<T>, B> {
+    /// Make a new `MaybeAtomicRefMut` for the contents of the borrowed `Option`, if any.
+    #[inline]
+    pub fn transpose(
+        orig: MaybeAtomicRefMut<'b, Option<T>, B>,
+    ) -> Option<MaybeAtomicRefMut<'b, T, B>> {
+        MaybeAtomicRefMut::filter_map(orig, Option::as_mut)
+    }
+}
+
+impl<'b, T: ?Sized, B> Deref for MaybeAtomicRefMut<'b, T, B> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        unsafe { self.value.as_ref() }
+    }
+}
+
+impl<'b, T: ?Sized, B> DerefMut for MaybeAtomicRefMut<'b, T, B> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { self.value.as_mut() }
+    }
+}
+
+impl<'b, T: ?Sized, B> AsRef<T> for MaybeAtomicRef<'b, T, B> {
+    #[inline]
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<'b, T: ?Sized, B> Borrow<T> for MaybeAtomicRef<'b, T, B> {
+    #[inline]
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<'b, T: ?Sized, B> AsRef<T> for MaybeAtomicRefMut<'b, T, B> {
+    #[inline]
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<'b, T: ?Sized, B> AsMut<T> for MaybeAtomicRefMut<'b, T, B> {
+    #[inline]
+    fn as_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+impl<'b, T: ?Sized, B> Borrow<T> for MaybeAtomicRefMut<'b, T, B> {
+    #[inline]
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<'b, T: ?Sized, B> BorrowMut<T> for MaybeAtomicRefMut<'b, T, B> {
+    #[inline]
+    fn borrow_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+// The guards are `Send` and `Sync` exactly when the references they wrap (and their borrows) are
+// (they hold a raw pointer, so they wouldn't be otherwise).
+unsafe impl<'b, T: ?Sized + Sync, B: Send> Send for MaybeAtomicRef<'b, T, B> {}
+unsafe impl<'b, T: ?Sized + Sync, B: Sync> Sync for MaybeAtomicRef<'b, T, B> {}
+unsafe impl<'b, T: ?Sized + Send, B: Send> Send for MaybeAtomicRefMut<'b, T, B> {}
+unsafe impl<'b, T: ?Sized + Sync, B: Sync> Sync for MaybeAtomicRefMut<'b, T, B> {}
+
+#[cfg(feature = "nightly")]
+impl<'b, T: ?Sized + Unsize<U>, U: ?Sized, B> CoerceUnsized<MaybeAtomicRef<'b, U, B>>
+    for MaybeAtomicRef<'b, T, B>
+{
+}
+
+#[cfg(feature = "nightly")]
+impl<'b, T: ?Sized + Unsize<U>, U: ?Sized, B> CoerceUnsized<MaybeAtomicRefMut<'b, U, B>>
+    for MaybeAtomicRefMut<'b, T, B>
+{
+}
+
+/// Unsizes a guard (e.g. `MaybeAtomicRef<[T; N]>` to `MaybeAtomicRef<[T]>`) on stable Rust,
+/// without the `nightly` feature. Boxed cells don't need it, since they unsize like any `Box`.
+///
+/// ```
+/// use maybe_atomic_refcell::{coerce, MaybeAtomicRef, MaybeAtomicRefCell};
+///
+/// let array = MaybeAtomicRefCell::new([1, 2, 3]);
+/// let slice: MaybeAtomicRef<[i32]> = coerce!(unsafe { array.borrow() } => [i32]);
+/// assert_eq!(slice.len(), 3);
+/// ```
+///
+/// Only unsizing coercions are accepted:
+///
+/// ```compile_fail
+/// use maybe_atomic_refcell::{coerce, MaybeAtomicRefCell};
+///
+/// let cell = MaybeAtomicRefCell::new(5u32);
+/// let _ = coerce!(unsafe { cell.borrow() } => [u8; 4]);
+/// ```
+#[macro_export]
+macro_rules! coerce {
+    ($value:expr => $target:ty) => {
+        match $value {
+            value => {
+                // SAFETY: The closure can only coerce the pointer it is given.
+                #[allow(unused_unsafe)]
+                let coerced = unsafe {
+                    $crate::__private::Coerce::<$target>::coerce(value, |value| -> *mut $target {
+                        value
+                    })
+                };
+                coerced
+            }
+        }
+    };
+}
+
+#[doc(hidden)]
+pub mod __private {
+    use crate::{MaybeAtomicRef, MaybeAtomicRefMut};
+    use std::marker::PhantomData;
+    use std::ptr::NonNull;
+
+    /// The types `coerce!` unsizes to `Output`, given their pointer to a `Target`.
+    pub trait Coerce<U: ?Sized> {
+        type Target: ?Sized;
+        type Output;
+
+        /// # Safety
+        ///
+        /// `f` must return the pointer it is given, unsized.
+        unsafe fn coerce(self, f: impl FnOnce(*mut Self::Target) -> *mut U) -> Self::Output;
+    }
+
+    impl<'b, T: ?Sized, U: ?Sized + 'b, B> Coerce<U> for MaybeAtomicRef<'b, T, B> {
+        type Target = T;
+        type Output = MaybeAtomicRef<'b, U, B>;
+
+        #[inline]
+        unsafe fn coerce(self, f: impl FnOnce(*mut T) -> *mut U) -> MaybeAtomicRef<'b, U, B> {
+            MaybeAtomicRef {
+                value: unsafe { NonNull::new_unchecked(f(self.value.as_ptr())) },
+                borrow: self.borrow,
+                marker: PhantomData,
+            }
+        }
+    }
+
+    impl<'b, T: ?Sized, U: ?Sized + 'b, B> Coerce<U> for MaybeAtomicRefMut<'b, T, B> {
+        type Target = T;
+        type Output = MaybeAtomicRefMut<'b, U, B>;
+
+        #[inline]
+        unsafe fn coerce(self, f: impl FnOnce(*mut T) -> *mut U) -> MaybeAtomicRefMut<'b, U, B> {
+            MaybeAtomicRefMut {
+                value: unsafe { NonNull::new_unchecked(f(self.value.as_ptr())) },
+                borrow: self.borrow,
+                marker: PhantomData,
+            }
+        }
+    }
+}
+
+impl<'b, T: ?Sized + Debug + 'b, B> Debug for MaybeAtomicRef<'b, T, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<'b, T: ?Sized + Debug + 'b, B> Debug for MaybeAtomicRefMut<'b, T, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<T: ?Sized + defmt::Format> defmt::Format for MaybeAtomicRefCell<T> {
+    fn format(&self, f: defmt::Formatter) {
+        #[cfg(any(debug_assertions, feature = "safe"))]
+        match fixed::try_borrow(&self.inner) {
+            Some(borrow) => defmt::write!(f, "MaybeAtomicRefCell {{ value: {} }}", &*borrow),
+            None => defmt::write!(f, "MaybeAtomicRefCell {{ value: <mutably borrowed> }}"),
+        }
+        #[cfg(not(any(debug_assertions, feature = "safe")))]
+        defmt::write!(f, "MaybeAtomicRefCell {{ value: <unchecked> }}")
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<'b, T: ?Sized + defmt::Format + 'b, B> defmt::Format for MaybeAtomicRef<'b, T, B> {
+    fn format(&self, f: defmt::Formatter) {
+        (**self).format(f)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<'b, T: ?Sized + defmt::Format + 'b, B> defmt::Format for MaybeAtomicRefMut<'b, T, B> {
+    fn format(&self, f: defmt::Formatter) {
+        (**self).format(f)
+    }
+}
+
+// The value behind a guard doesn't move when the guard does. `CloneStableDeref` isn't
+// implemented, since it would need the guards to implement `Clone`, shadowing `T::clone`.
+#[cfg(feature = "stable_deref_trait")]
+unsafe impl<'b, T: ?Sized + 'b, B> stable_deref_trait::StableDeref for MaybeAtomicRef<'b, T, B> {}
+
+#[cfg(feature = "stable_deref_trait")]
+unsafe impl<'b, T: ?Sized + 'b, B> stable_deref_trait::StableDeref for MaybeAtomicRefMut<'b, T, B> {}
+
+macro_rules! impl_guard_cmp {
+    ($guard:ident) => {
+        impl<'b, T: ?Sized + PartialEq, B> PartialEq<T> for $guard<'b, T, B> {
+            #[inline]
+            fn eq(&self, other: &T) -> bool {
+                **self == *other
+            }
+        }
+
+        impl<'b, T: ?Sized + PartialOrd, B> PartialOrd<T> for $guard<'b, T, B> {
+            #[inline]
+            fn partial_cmp(&self, other: &T) -> Option<Ordering> {
+                (**self).partial_cmp(other)
+            }
+        }
+
+        impl<'b, T: ?Sized + Eq, B> Eq for $guard<'b, T, B> {}
+
+        impl<'b, T: ?Sized + Ord, B> Ord for $guard<'b, T, B> {
+            #[inline]
+            fn cmp(&self, other: &Self) -> Ordering {
+                (**self).cmp(&**other)
+            }
+        }
+
+        impl_guard_cmp!($guard, MaybeAtomicRef);
+        impl_guard_cmp!($guard, MaybeAtomicRefMut);
+    };
+    ($guard:ident, $other:ident) => {
+        impl<'a, 'b, T: ?Sized + PartialEq, B, C> PartialEq<$other<'a, T, C>> for $guard<'b, T, B> {
+            #[inline]
+            fn eq(&self, other: &$other<'a, T, C>) -> bool {
+                **self == **other
+            }
+        }
+
+        impl<'a, 'b, T: ?Sized + PartialOrd, B, C> PartialOrd<$other<'a, T, C>>
+            for $guard<'b, T, B>
+        {
+            #[inline]
+            fn partial_cmp(&self, other: &$other<'a, T, C>) -> Option<Ordering> {
+                (**self).partial_cmp(&**other)
+            }
+        }
+    };
+}
+
+impl_guard_cmp!(MaybeAtomicRef);
+impl_guard_cmp!(MaybeAtomicRefMut);
+
+impl<'b, T: ?Sized + Display + 'b, B> Display for MaybeAtomicRef<'b, T, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<'b, T: ?Sized + Display + 'b, B> Display for MaybeAtomicRefMut<'b, T, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<T: ?Sized + Debug> Debug for MaybeAtomicRefCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Shown in place of a value that can't be safely looked at.
+        struct Placeholder(&'static str);
+
+        impl Debug for Placeholder {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(self.0)
+            }
+        }
+
+        let mut debug = f.debug_struct("MaybeAtomicRefCell");
+        #[cfg(any(debug_assertions, feature = "safe"))]
+        match fixed::try_borrow(&self.inner) {
+            Some(borrow) => debug.field("value", &&*borrow),
+            None => debug.field("value", &Placeholder("<mutably borrowed>")),
+        };
+        #[cfg(not(any(debug_assertions, feature = "safe")))]
+        debug.field("value", &Placeholder("<unchecked>"));
+        debug.finish()
+    }
+}
+
+/// Mutably borrows a struct inside a `MaybeAtomicRefCell` once, and binds a `&mut` reference
+/// to each of the named (disjoint) fields. The borrow lasts until the end of the enclosing
+/// scope.
+///
+/// Like `MaybeAtomicRefCell::borrow_mut`, this must be used in an `unsafe` context.
+///
+/// ```
+/// use maybe_atomic_refcell::{split_borrow, MaybeAtomicRefCell};
+///
+/// struct World {
+///     physics: u32,
+///     render: u32,
+/// }
+///
+/// let cell = MaybeAtomicRefCell::new(World { physics: 1, render: 2 });
+/// unsafe {
+///     split_borrow!(cell => { physics, render });
+///     *physics += *render;
+/// }
+/// assert_eq!(cell.into_inner().physics, 3);
+/// ```
+#[macro_export]
+macro_rules! split_borrow {
+    ($cell:expr => { $($field:ident),+ $(,)? }) => {
+        let mut guard = $cell.borrow_mut();
+        let value = &mut *guard;
+        $(let $field = &mut value.$field;)+
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{MaybeAtomicRef, MaybeAtomicRefCell, MaybeAtomicRefMut};
+
+    #[test]
+    fn it_works() {
+        let cell = MaybeAtomicRefCell::new(5);
+
+        unsafe {
+            {
+                assert_eq!(*cell.borrow(), 5);
+                let _borrow1 = cell.borrow();
+                let _borrow2 = cell.borrow();
+            }
+
+            *cell.borrow_mut() += 1;
+
+            {
+                let _borrow1 = cell.borrow();
+                let _borrow2 = cell.borrow();
+                assert_eq!(*cell.borrow(), 6);
+            }
+
+            // Is Send.
+            std::thread::spawn(move || {
+                let mine = cell;
+                mine.borrow();
+
+                let inner = mine.into_inner();
+                assert_eq!(inner, 6);
+            })
+            .join()
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn try_map() {
+        use std::collections::HashMap;
+
+        fn lookup(map: &HashMap<i32, i32>, key: i32) -> Result<&i32, String> {
+            map.get(&key).ok_or(format!("no {key}"))
+        }
+
+        let cell = MaybeAtomicRefCell::new(HashMap::from([(1, 2)]));
+        unsafe {
+            let (guard, e) = MaybeAtomicRef::try_map(cell.borrow(), |m| lookup(m, 3)).unwrap_err();
+            assert_eq!((guard.len(), e.as_str()), (1, "no 3"));
+            assert_eq!(
+                *MaybeAtomicRef::try_map(guard, |m| lookup(m, 1)).unwrap(),
+                2
+            );
+
+            let guard = cell.borrow_mut();
+            let (guard, ()) =
+                MaybeAtomicRefMut::try_map(guard, |m| m.get_mut(&3).ok_or(())).unwrap_err();
+            *MaybeAtomicRefMut::try_map(guard, |m| m.get_mut(&1).ok_or(())).unwrap() += 1;
+            assert_eq!(cell.borrow()[&1], 3);
+        }
+    }
+
+    #[test]
+    fn unsized_coercions() {
+        let array: Box<MaybeAtomicRefCell<[i32; 3]>> = Box::new(MaybeAtomicRefCell::new([1, 2, 3]));
+        let slice: Box<MaybeAtomicRefCell<[i32]>> = array;
+        let debug: Box<MaybeAtomicRefCell<dyn std::fmt::Debug>> =
+            Box::new(MaybeAtomicRefCell::new(5));
+
+        unsafe {
+            MaybeAtomicRefMut::map(slice.borrow_mut(), |s| &mut s[1..])[0] = 4;
+            assert_eq!(&*slice.borrow(), &[1, 4, 3]);
+            assert_eq!(format!("{:?}", debug.borrow()), "5");
+        }
+    }
+
+    #[test]
+    fn stable_coercions() {
+        let array = MaybeAtomicRefCell::new([1, 2, 3]);
+
+        unsafe {
+            crate::coerce!(array.borrow_mut() => [i32])[2] = 4;
+            let borrow: MaybeAtomicRef<[i32]> = crate::coerce!(array.borrow() => [i32]);
+            assert_eq!(&*borrow, &[1, 2, 4]);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "nightly")]
+    fn guard_coercions() {
+        let cell = MaybeAtomicRefCell::new([1, 2, 3]);
+
+        unsafe {
+            {
+                let mut borrow: MaybeAtomicRefMut<[i32]> = cell.borrow_mut();
+                borrow[0] = 0;
+            }
+            let borrow: MaybeAtomicRef<[i32]> = cell.borrow();
+            assert_eq!(&*borrow, &[0, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn many_mut() {
+        let a = MaybeAtomicRefCell::new(1);
+        let b = MaybeAtomicRefCell::new(2);
+        let c = MaybeAtomicRefCell::new(3);
+
+        unsafe {
+            let [mut a, mut b, c] = MaybeAtomicRefCell::borrow_many_mut([&a, &b, &c]);
+            *a += *c;
+            *b += *c;
+        }
+
+        assert_eq!((a.into_inner(), b.into_inner()), (4, 5));
+    }
+
+    #[test]
+    fn borrow_mut_both() {
+        let cells = [MaybeAtomicRefCell::new(1), MaybeAtomicRefCell::new(2)];
+        unsafe {
+            // Guards come back in argument order, whatever the acquisition order.
+            let (mut b, mut a) = MaybeAtomicRefCell::borrow_mut_both(&cells[1], &cells[0]);
+            std::mem::swap(&mut *a, &mut *b);
+            assert_eq!((*a, *b), (2, 1));
+        }
+        assert_eq!(cells.map(MaybeAtomicRefCell::into_inner), [2, 1]);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(
+        any(debug_assertions, feature = "safe"),
+        should_panic(expected = "cells 0 and 2 are the same")
+    )]
+    fn it_panics_many_mut_duplicate() {
+        let a = MaybeAtomicRefCell::new(1);
+        let b = MaybeAtomicRefCell::new(2);
+        unsafe {
+            let _borrows = MaybeAtomicRefCell::borrow_many_mut([&a, &b, &a]);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(
+        any(debug_assertions, feature = "safe"),
+        should_panic(expected = "cell 1 is already borrowed")
+    )]
+    fn it_panics_many_mut_borrowed() {
+        let a = MaybeAtomicRefCell::new(1);
+        let b = MaybeAtomicRefCell::new(2);
+        unsafe {
+            let _borrow = b.borrow();
+            let _borrows = MaybeAtomicRefCell::borrow_many_mut([&a, &b]);
+        }
+    }
+
+    #[test]
+    fn split_borrow() {
+        struct World {
+            physics: Vec<u32>,
+            render: String,
+            audio: bool,
+        }
+
+        let cell = MaybeAtomicRefCell::new(World {
+            physics: vec![1],
+            render: String::from("a"),
+            audio: false,
+        });
+
+        unsafe {
+            split_borrow!(cell => { physics, render, audio });
+            physics.push(2);
+            render.push('b');
+            *audio = true;
+        }
+
+        let world = cell.into_inner();
+        assert_eq!(world.physics, [1, 2]);
+        assert_eq!(world.render, "ab");
+        assert!(world.audio);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(any(debug_assertions, feature = "safe"), should_panic)]
+    fn it_panics_split_borrow_ref() {
+        struct Pair {
+            a: u32,
+            b: u32,
+        }
+
+        let cell = MaybeAtomicRefCell::new(Pair { a: 1, b: 2 });
+        unsafe {
+            split_borrow!(cell => { a, b });
+            let _borrow = cell.borrow();
+            *a += *b;
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "derive")]
+    fn derive_fields() {
+        #[derive(crate::MaybeAtomicFields)]
+        struct World<T: Clone> {
+            a: T,
+            pub b: Vec<T>,
+        }
+
+        let cells = WorldCells::new(World { a: 1, b: vec![2] });
+
+        unsafe {
+            let a = cells.borrow_a();
+            cells.borrow_b_mut().push(*a);
+            assert_eq!(*cells.b.borrow(), [2, 1]);
+        }
+
+        let world = WorldCells::from(cells.into_inner()).into_inner();
+        assert_eq!((world.a, world.b), (1, vec![2, 1]));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde() {
+        let cell = MaybeAtomicRefCell::new(vec![1, 2]);
+        let json = serde_json::to_string(&cell).unwrap();
+        assert_eq!(json, "[1,2]");
+
+        let cell: MaybeAtomicRefCell<Vec<i32>> = serde_json::from_str(&json).unwrap();
+        assert_eq!(cell.into_inner(), [1, 2]);
+    }
+
+    #[test]
+    #[cfg(all(feature = "serde", any(debug_assertions, feature = "safe")))]
+    fn serde_mutably_borrowed() {
+        let cell = MaybeAtomicRefCell::new(5);
+        let _borrow = unsafe { cell.borrow_mut() };
+        assert!(serde_json::to_string(&cell).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "rkyv")]
+    fn rkyv() {
+        use crate::ArchivedMaybeAtomicRefCell;
+        use rkyv::{rancor::Error, vec::ArchivedVec, Archived};
+
+        let cell = MaybeAtomicRefCell::new(vec![1u32, 2]);
+        let bytes = rkyv::to_bytes::<Error>(&cell).unwrap();
+
+        let archived =
+            rkyv::access::<ArchivedMaybeAtomicRefCell<ArchivedVec<Archived<u32>>>, Error>(&bytes)
+                .unwrap();
+        assert_eq!(archived.as_slice(), [1, 2]);
+
+        let cell: MaybeAtomicRefCell<Vec<u32>> = rkyv::deserialize::<_, Error>(archived).unwrap();
+        assert_eq!(cell.into_inner(), [1, 2]);
+    }
+
+    #[test]
+    #[cfg(feature = "arbitrary")]
+    fn arbitrary() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let bytes = [1, 2, 3, 4];
+        let cell = MaybeAtomicRefCell::<u32>::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+        assert_eq!(
+            cell.into_inner(),
+            u32::arbitrary(&mut Unstructured::new(&bytes)).unwrap()
+        );
+        assert_eq!(MaybeAtomicRefCell::<u32>::size_hint(0), u32::size_hint(0));
+    }
+
+    #[test]
+    #[cfg(feature = "proptest")]
+    fn proptest() {
+        use proptest::arbitrary::any;
+        use proptest::test_runner::TestRunner;
+
+        let mut runner = TestRunner::default();
+        runner
+            .run(&any::<MaybeAtomicRefCell<u8>>(), |cell| {
+                let _ = cell.into_inner();
+                Ok(())
+            })
+            .unwrap();
+        runner
+            .run(&MaybeAtomicRefCell::strategy(0..10u8), |cell| {
+                assert!(cell.into_inner() < 10);
+                Ok(())
+            })
+            .unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn par_iter_borrow() {
+        use crate::ParallelBorrowExt;
+        use rayon::iter::ParallelIterator;
+
+        let cells: Vec<_> = (0..100u32).map(MaybeAtomicRefCell::new).collect();
+        unsafe {
+            cells
+                .par_iter_borrow_mut()
+                .for_each(|mut value| *value *= 2);
+            assert_eq!(
+                cells.par_iter_borrow().map(|value| *value).sum::<u32>(),
+                9900
+            );
+        }
+    }
+
+    #[test]
+    fn iter_try_borrow() {
+        use crate::TryBorrowExt;
+
+        let cells: Vec<_> = (0..3u32).map(MaybeAtomicRefCell::new).collect();
+        unsafe {
+            for mut value in cells.iter_try_borrow_mut().flatten() {
+                *value += 1;
+            }
+            assert!(cells.iter_try_borrow().all(|value| value.is_some()));
+
+            #[cfg(any(debug_assertions, feature = "safe"))]
+            {
+                let _writing = cells[1].borrow_mut();
+                let _reading = cells[2].borrow();
+                let values: Vec<_> = cells
+                    .iter_try_borrow()
+                    .map(|value| value.map(|value| *value))
+                    .collect();
+                assert_eq!(values, [Some(1), None, Some(3)]);
+                let values: Vec<_> = cells
+                    .iter_try_borrow_mut()
+                    .map(|value| value.map(|value| *value))
+                    .collect();
+                assert_eq!(values, [Some(1), None, None]);
+            }
+        }
+    }
+
+    #[test]
+    fn borrow_downcast() {
+        use std::any::Any;
+
+        let cell: Box<MaybeAtomicRefCell<dyn Any + Send>> = Box::new(MaybeAtomicRefCell::new(5u32));
+        unsafe {
+            assert!(cell.borrow_downcast::<u8>().is_none());
+            *cell.borrow_mut_downcast::<u32>().unwrap() += 1;
+            assert_eq!(*cell.borrow_downcast::<u32>().unwrap(), 6);
+        }
+
+        let cell = MaybeAtomicRefCell::new(Box::new(String::from("foo")) as Box<dyn Any>);
+        unsafe {
+            assert!(cell.borrow_mut_downcast::<u32>().is_none());
+            cell.borrow_mut_downcast::<String>()
+                .unwrap()
+                .push_str("bar");
+            assert_eq!(*cell.borrow_downcast::<String>().unwrap(), "foobar");
+        }
+    }
+
+    #[test]
+    fn clone() {
+        let cell = MaybeAtomicRefCell::new(vec![1]);
+        let clone = cell.clone();
+        unsafe {
+            cell.borrow_mut().push(2);
+        }
+        assert_eq!(cell.into_inner(), [1, 2]);
+        assert_eq!(clone.into_inner(), [1]);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(any(debug_assertions, feature = "safe"), should_panic)]
+    fn it_panics_clone_mut() {
+        let cell = MaybeAtomicRefCell::new(5);
+        unsafe {
+            let _borrow = cell.borrow_mut();
+            let _clone = cell.clone();
+        }
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)]
+    fn comparisons() {
+        use std::collections::{BTreeSet, HashSet};
+
+        let a = MaybeAtomicRefCell::new(1);
+        let b = MaybeAtomicRefCell::new(2);
+        assert_eq!(a, MaybeAtomicRefCell::new(1));
+        assert_ne!(a, b);
+        assert!(a < b);
+        assert_eq!(a.cmp(&b), std::cmp::Ordering::Less);
+
+        let hashed: HashSet<_> = [a.clone(), b.clone(), a.clone()].into_iter().collect();
+        assert_eq!(hashed.len(), 2);
+        let sorted: BTreeSet<_> = [b, a].into_iter().collect();
+        assert_eq!(
+            sorted
+                .into_iter()
+                .map(MaybeAtomicRefCell::into_inner)
+                .collect::<Vec<_>>(),
+            [1, 2]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(any(debug_assertions, feature = "safe"), should_panic)]
+    fn it_panics_eq_mut() {
+        let cell = MaybeAtomicRefCell::new(5);
+        unsafe {
+            let _borrow = cell.borrow_mut();
+            let _ = cell == MaybeAtomicRefCell::new(5);
+        }
+    }
+
+    #[test]
+    fn const_new() {
+        static CELL: MaybeAtomicRefCell<[u32; 2]> = MaybeAtomicRefCell::new([1, 2]);
+
+        unsafe {
+            CELL.borrow_mut()[0] = 3;
+            assert_eq!(*CELL.borrow(), [3, 2]);
+        }
+    }
+
+    #[test]
+    fn debug() {
+        let cell = MaybeAtomicRefCell::new(5);
+
+        #[cfg(any(debug_assertions, feature = "safe"))]
+        unsafe {
+            assert_eq!(format!("{:?}", cell), "MaybeAtomicRefCell { value: 5 }");
+            let _borrow = cell.borrow();
+            assert_eq!(format!("{:?}", cell), "MaybeAtomicRefCell { value: 5 }");
+            drop(_borrow);
+            let _borrow = cell.borrow_mut();
+            assert_eq!(
+                format!("{:?}", cell),
+                "MaybeAtomicRefCell { value: <mutably borrowed> }"
+            );
+        }
+        #[cfg(not(any(debug_assertions, feature = "safe")))]
+        assert_eq!(
+            format!("{:?}", cell),
+            "MaybeAtomicRefCell { value: <unchecked> }"
+        );
+    }
+
+    #[test]
+    fn display_guards() {
+        let cell = MaybeAtomicRefCell::new(String::from("hello"));
+        unsafe {
+            assert_eq!(format!("{:>6}", cell.borrow()), " hello");
+            assert_eq!(format!("{}", cell.borrow_mut()), "hello");
+        }
+    }
+
+    #[test]
+    fn compare_guards() {
+        let a = MaybeAtomicRefCell::new(1);
+        let b = MaybeAtomicRefCell::new(2);
+
+        unsafe {
+            assert_eq!(a.borrow(), 1);
+            assert_eq!(a.borrow_mut(), 1);
+            assert!(a.borrow() < 2);
+            assert!(b.borrow_mut() > 1);
+
+            assert_ne!(a.borrow(), b.borrow());
+            assert!(a.borrow() < b.borrow_mut());
+            assert!(a.borrow_mut() < b.borrow());
+            assert_eq!(a.borrow().cmp(&b.borrow()), std::cmp::Ordering::Less);
+        }
+    }
+
+    #[test]
+    fn as_ref_guards() {
+        use std::borrow::{Borrow, BorrowMut};
+
+        fn len(s: impl AsRef<String>) -> usize {
+            s.as_ref().len()
+        }
+        fn push(mut v: impl AsMut<Vec<i32>>) {
+            v.as_mut().push(0);
+        }
+        fn first<T: Borrow<[i32]>>(v: T) -> i32 {
+            v.borrow()[0]
+        }
+        fn clear<T: BorrowMut<Vec<i32>>>(mut v: T) {
+            v.borrow_mut().clear();
+        }
+
+        let name = MaybeAtomicRefCell::new(String::from("abc"));
+        let cell = MaybeAtomicRefCell::new(vec![1]);
+        unsafe {
+            assert_eq!(len(name.borrow()), 3);
+            assert_eq!(len(name.borrow_mut()), 3);
+            push(cell.borrow_mut());
+            assert_eq!(first(MaybeAtomicRef::map(cell.borrow(), Vec::as_slice)), 1);
+            clear(cell.borrow_mut());
+        }
+        assert!(cell.into_inner().is_empty());
+    }
+
+    #[test]
+    fn send_guards() {
+        let cell = MaybeAtomicRefCell::new(vec![1]);
+
+        unsafe {
+            let mut borrow = cell.borrow_mut();
+            std::thread::scope(|s| {
+                s.spawn(move || borrow.push(2));
+            });
+
+            let borrow = cell.borrow();
+            std::thread::scope(|s| {
+                s.spawn(move || assert_eq!(*borrow, [1, 2]));
+            });
+        }
+    }
+
+    #[test]
+    fn sync_guards() {
+        let cell = MaybeAtomicRefCell::new(vec![1, 2, 3]);
+
+        unsafe {
+            let borrow = cell.borrow();
+            let borrow = &borrow;
+            std::thread::scope(|s| {
+                for i in 0..3 {
+                    s.spawn(move || assert_eq!(borrow[i], i + 1));
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn raw_get() {
+        use std::mem::MaybeUninit;
+
+        static CELL: MaybeAtomicRefCell<MaybeUninit<u32>> =
+            MaybeAtomicRefCell::new(MaybeUninit::uninit());
+
+        unsafe {
+            let ptr = MaybeAtomicRefCell::raw_get(&CELL);
+            assert_eq!(ptr, CELL.as_ptr());
+            ptr.write(MaybeUninit::new(5));
+            assert_eq!(CELL.borrow().assume_init(), 5);
+        }
+    }
+
+    #[test]
+    fn as_non_null() {
+        let cell = MaybeAtomicRefCell::new(5);
+        let ptr = cell.as_non_null();
+        assert_eq!(ptr.as_ptr(), cell.as_ptr());
+        unsafe {
+            *ptr.as_ptr() += 1;
+        }
+        assert_eq!(cell.into_inner(), 6);
+    }
+
+    #[test]
+    #[cfg(all(
+        any(not(any(debug_assertions, feature = "safe")), feature = "side_table"),
+        not(feature = "change_detection")
+    ))]
+    fn transparent_layout() {
+        use std::mem::{align_of, size_of};
+
+        fn assert_layout<T>() {
+            assert_eq!(size_of::<MaybeAtomicRefCell<T>>(), size_of::<T>());
+            assert_eq!(align_of::<MaybeAtomicRefCell<T>>(), align_of::<T>());
+        }
+        assert_layout::<u8>();
+        assert_layout::<u64>();
+        assert_layout::<[u16; 3]>();
+        assert_layout::<String>();
+        assert_eq!(size_of::<&MaybeAtomicRefCell<[u32]>>(), size_of::<&[u32]>());
+    }
+
+    #[test]
+    #[cfg(not(any(debug_assertions, feature = "safe")))]
+    fn zero_overhead() {
+        use std::mem::size_of;
+
+        fn assert_guards<T: ?Sized + 'static>() {
+            assert_eq!(size_of::<MaybeAtomicRef<'_, T>>(), size_of::<&T>());
+            assert_eq!(size_of::<MaybeAtomicRefMut<'_, T>>(), size_of::<&mut T>());
+            assert_eq!(size_of::<Option<crate::MaybeRef<'_, T>>>(), size_of::<&T>());
+        }
+        assert_guards::<u8>();
+        assert_guards::<String>();
+        assert_guards::<[u64]>();
+        assert_guards::<dyn std::any::Any + Send>();
+
+        // Borrows are the pointer and nothing else.
+        let cell = MaybeAtomicRefCell::new(1);
+        unsafe {
+            let guard = cell.borrow_mut();
+            assert!(std::ptr::eq(&*guard, cell.as_ptr()));
+        }
+    }
+
+    #[test]
+    #[cfg(all(
+        feature = "side_table",
+        any(debug_assertions, feature = "safe"),
+        not(feature = "change_detection")
+    ))]
+    fn side_table_nested() {
+        #[repr(C)]
+        struct Outer {
+            inner: MaybeAtomicRefCell<u32>,
+            other: u32,
+        }
+
+        let outer = MaybeAtomicRefCell::new(Outer {
+            inner: MaybeAtomicRefCell::new(1),
+            other: 2,
+        });
+        unsafe {
+            let guard = outer.borrow();
+            // The inner cell has the same address, but is a different cell.
+            assert_eq!(outer.as_ptr() as usize, guard.inner.as_ptr() as usize);
+            *guard.inner.borrow_mut() += guard.other;
+        }
+        assert_eq!(outer.into_inner().inner.into_inner(), 3);
+
+        // Same address and size, only the type differs.
+        let outer = MaybeAtomicRefCell::new(MaybeAtomicRefCell::new(1u32));
+        unsafe {
+            let guard = outer.borrow();
+            *guard.borrow_mut() += 1;
+        }
+        assert_eq!(outer.into_inner().into_inner(), 2);
+
+        #[repr(C)]
+        struct Empty {
+            a: MaybeAtomicRefCell<()>,
+            b: MaybeAtomicRefCell<[u8; 0]>,
+        }
+
+        let empty = Empty {
+            a: MaybeAtomicRefCell::new(()),
+            b: MaybeAtomicRefCell::new([]),
+        };
+        assert_eq!(empty.a.as_ptr() as usize, empty.b.as_ptr() as usize);
+        unsafe {
+            let _a = empty.a.borrow_mut();
+            let _b = empty.b.borrow_mut();
+        }
+    }
+
+    #[test]
+    #[cfg(all(
+        feature = "side_table",
+        any(debug_assertions, feature = "safe"),
+        any(feature = "blocking", not(feature = "atomic_refcell")),
+        not(feature = "change_detection")
+    ))]
+    fn side_table_nested_waits() {
+        use std::sync::mpsc;
+        use std::time::Duration;
+
+        let outer = &MaybeAtomicRefCell::new(MaybeAtomicRefCell::new(1u32));
+        std::thread::scope(|scope| {
+            let (borrowed_tx, borrowed_rx) = mpsc::channel();
+            scope.spawn(move || {
+                let guard = unsafe { outer.borrow() };
+                let mut inner = unsafe { guard.borrow_mut() };
+                borrowed_tx.send(()).unwrap();
+                std::thread::sleep(Duration::from_millis(50));
+                *inner += 1;
+            });
+            borrowed_rx.recv().unwrap();
+            // This thread holds a borrow of the outer cell, at the same address, but the inner
+            // one is borrowed by the other thread, so this waits rather than being reentrant.
+            let guard = unsafe { outer.borrow() };
+            assert_eq!(*unsafe { guard.borrow() }, 2);
+        });
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        let cell = Box::new(MaybeAtomicRefCell::new(vec![1]));
+        let ptr = MaybeAtomicRefCell::into_raw(cell);
+
+        unsafe {
+            (*ptr).push(2);
+            let cell = MaybeAtomicRefCell::from_raw(ptr);
+            cell.borrow_mut().push(3);
+
+            let ptr = MaybeAtomicRefCell::into_raw(cell);
+            assert_eq!(*Box::from_raw(ptr), [1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn boxed_round_trip() {
+        let cell = MaybeAtomicRefCell::from_boxed(Box::new([String::from("a"), String::from("b")]));
+        let cell: Box<MaybeAtomicRefCell<[String]>> = cell;
+        unsafe {
+            cell.borrow_mut()[1].push('c');
+        }
+        assert_eq!(*cell.into_inner_boxed(), ["a", "bc"]);
+
+        let cell: Box<MaybeAtomicRefCell<dyn std::any::Any>> =
+            Box::new(MaybeAtomicRefCell::new(7u16));
+        let value = cell.into_inner_boxed();
+        assert_eq!(value.downcast_ref(), Some(&7u16));
+        let cell = MaybeAtomicRefCell::from_boxed(value.downcast::<u16>().unwrap());
+        let cell: Box<MaybeAtomicRefCell<dyn std::any::Any>> = cell;
+        assert!(unsafe { cell.borrow().is::<u16>() });
+
+        let empty = MaybeAtomicRefCell::from_boxed(Box::new([0u64; 0]));
+        let empty: Box<MaybeAtomicRefCell<[u64]>> = empty;
+        assert!(empty.into_inner_boxed().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "change_detection")]
+    fn change_detection() {
+        let mut cell = MaybeAtomicRefCell::new(vec![1]);
+        assert!(!cell.is_changed());
+
+        assert_eq!(unsafe { cell.borrow() }.len(), 1);
+        assert!(!cell.is_changed());
+        unsafe { cell.borrow_mut() }.push(2);
+        assert!(cell.is_changed());
+        assert!(cell.reset_changed());
+        assert!(!cell.is_changed() && !cell.reset_changed());
+
+        cell.get_mut().push(3);
+        assert!(cell.reset_changed());
+        assert_eq!(cell.into_inner(), [1, 2, 3]);
+    }
+
+    #[test]
+    #[cfg(feature = "change_detection")]
+    fn change_detection_many() {
+        use crate::borrow_all;
+
+        let cells: Vec<_> = (0..3u32).map(MaybeAtomicRefCell::new).collect();
+        let other = MaybeAtomicRefCell::new(0.0);
+        let reset = || {
+            for cell in &cells {
+                cell.reset_changed();
             }
+            other.reset_changed();
+        };
 
-            *cell.borrow_mut() += 1;
+        unsafe {
+            let _guards = MaybeAtomicRefCell::borrow_mut_both(&cells[0], &cells[2]);
+        }
+        let changed = |cells: &[MaybeAtomicRefCell<u32>]| {
+            cells
+                .iter()
+                .map(|cell| cell.is_changed())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(changed(&cells), [true, false, true]);
+
+        reset();
+        unsafe {
+            let _guards = borrow_all((&cells[1], &other));
+        }
+        assert_eq!(changed(&cells), [false, true, false]);
+        assert!(other.is_changed());
+
+        // Only cells that were actually borrowed.
+        #[cfg(any(debug_assertions, feature = "safe"))]
+        {
+            use crate::TryBorrowExt;
+
+            reset();
+            unsafe {
+                let _reading = cells[1].borrow();
+                drop(cells.iter_try_borrow_mut().collect::<Vec<_>>());
+            }
+            assert_eq!(changed(&cells), [true, false, true]);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "change_detection")]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    fn change_detection_failed_borrow() {
+        let cell = MaybeAtomicRefCell::new(1);
+        let _reading = unsafe { cell.borrow() };
+        #[cfg(any(debug_assertions, feature = "safe"))]
+        {
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe {
+                drop(cell.borrow_mut());
+            }));
+            assert!(result.is_err());
+        }
+        assert!(!cell.is_changed());
+    }
+
+    #[test]
+    fn get_cloned() {
+        let cell = MaybeAtomicRefCell::new(vec![1]);
+        unsafe {
+            let mut cloned = cell.get_cloned();
+            cloned.push(2);
+            cell.borrow_mut().push(3);
+            assert_eq!(cloned, [1, 2]);
+        }
+        assert_eq!(cell.into_inner(), [1, 3]);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(any(debug_assertions, feature = "safe"), should_panic)]
+    fn it_panics_get_cloned_mut() {
+        let cell = MaybeAtomicRefCell::new(5);
+        unsafe {
+            let _borrow = cell.borrow_mut();
+            cell.get_cloned();
+        }
+    }
+
+    #[test]
+    fn uninit() {
+        use std::mem::MaybeUninit;
+
+        let cell = MaybeAtomicRefCell::<MaybeUninit<String>>::uninit();
+        unsafe {
+            cell.write(String::from("hello"));
+            assert_eq!(cell.assume_init().into_inner(), "hello");
+        }
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(any(debug_assertions, feature = "safe"), should_panic)]
+    fn it_panics_write_ref() {
+        use std::mem::MaybeUninit;
+
+        let cell = MaybeAtomicRefCell::<MaybeUninit<u32>>::uninit();
+        unsafe {
+            let _borrow = cell.borrow();
+            cell.write(5);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "bytemuck")]
+    fn zeroed() {
+        let cell = MaybeAtomicRefCell::<[u64; 4]>::zeroed();
+        assert_eq!(cell.into_inner(), [0; 4]);
+    }
+
+    #[test]
+    #[cfg(feature = "defmt")]
+    fn defmt() {
+        fn assert_format<T: defmt::Format + ?Sized>() {}
+
+        assert_format::<MaybeAtomicRefCell<u32>>();
+        assert_format::<MaybeAtomicRefCell<[u32]>>();
+        assert_format::<MaybeAtomicRefMut<str>>();
+        assert_format::<MaybeAtomicRef<u32>>();
+    }
+
+    #[test]
+    #[cfg(feature = "stable_deref_trait")]
+    fn stable_deref() {
+        fn assert_stable_deref<T: stable_deref_trait::StableDeref>() {}
+
+        assert_stable_deref::<MaybeAtomicRef<[u32]>>();
+        assert_stable_deref::<MaybeAtomicRefMut<str>>();
+        assert_stable_deref::<MaybeAtomicRef<u32, crate::UncheckedBorrow>>();
+    }
 
+    #[test]
+    #[cfg(feature = "atomic_refcell")]
+    fn conversions() {
+        let cell: MaybeAtomicRefCell<i32> = atomic_refcell::AtomicRefCell::new(1).into();
+        let cell = MaybeAtomicRefCell::new(cell.into_atomic_ref_cell().into_inner() + 1);
+        let cell: MaybeAtomicRefCell<i32> = std::cell::RefCell::new(cell.into_inner() + 1).into();
+        assert_eq!(cell.into_ref_cell().into_inner(), 3);
+    }
+
+    #[test]
+    #[cfg(all(
+        feature = "atomic_refcell",
+        any(debug_assertions, feature = "safe"),
+        not(any(feature = "blocking", feature = "side_table"))
+    ))]
+    fn atomic_ref_conversions() {
+        use crate::storage::MaybeAtomicArena;
+
+        let cell = MaybeAtomicRefCell::new(vec![1]);
+        assert_eq!(*cell.as_atomic_ref_cell().unwrap().borrow(), [1]);
+        unsafe {
+            let mut guard = MaybeAtomicRefMut::into_atomic_ref_mut(cell.borrow_mut()).unwrap();
+            guard.push(2);
+            assert!(cell.as_atomic_ref_cell().unwrap().try_borrow().is_err());
+            drop(guard);
+
+            let guard = MaybeAtomicRef::into_atomic_ref(cell.borrow()).unwrap();
+            assert_eq!(*guard, *cell.borrow());
+            drop(guard);
+
+            let (a, _b) = MaybeAtomicRefMut::map_split(cell.borrow_mut(), |v| v.split_at_mut(1));
+            assert!(MaybeAtomicRefMut::into_atomic_ref_mut(a).is_err());
+
+            let arena = MaybeAtomicArena::from(vec![1]);
+            assert!(MaybeAtomicRef::into_atomic_ref(arena.borrow(0)).is_err());
+        }
+    }
+
+    #[test]
+    fn option_transpose() {
+        let cell = MaybeAtomicRefCell::new(Some(1));
+        let none = MaybeAtomicRefCell::new(None::<i32>);
+
+        unsafe {
+            *cell.borrow_some_mut().unwrap() += 1;
+            assert_eq!(MaybeAtomicRef::transpose(cell.borrow()).unwrap(), 2);
+            assert_eq!(*cell.borrow_some().unwrap(), 2);
+
+            assert!(none.borrow_some().is_none());
+            assert!(MaybeAtomicRefMut::transpose(none.borrow_mut()).is_none());
+            // The borrow is released when there is no value.
+            *none.borrow_mut() = Some(3);
+        }
+        assert_eq!(none.into_inner(), Some(3));
+    }
+
+    #[test]
+    fn borrow_iter() {
+        let cell = MaybeAtomicRefCell::new(vec![1, 2, 3]);
+
+        unsafe {
+            for mut value in cell.borrow_iter_mut() {
+                *value *= 10;
+            }
+            let (first, last) = MaybeAtomicRef::map_split(cell.borrow(), |v| (&v[0], &v[2]));
+            assert_eq!((*first, *last), (10, 30));
+            assert_eq!(
+                cell.borrow_iter().rev().map(|v| *v).collect::<Vec<_>>(),
+                [30, 20, 10]
+            );
+        }
+
+        let cell: &MaybeAtomicRefCell<[i32]> = &MaybeAtomicRefCell::new([1, 2]);
+        unsafe {
             {
-                let _borrow1 = cell.borrow();
-                let _borrow2 = cell.borrow();
-                assert_eq!(*cell.borrow(), 6);
+                let mut values: Vec<_> = cell.borrow_iter_mut().collect();
+                *values[1] += *values[0];
             }
+            assert_eq!(cell.borrow_iter().map(|v| *v).sum::<i32>(), 4);
+        }
+    }
 
-            // Is Send.
-            std::thread::spawn(move || {
-                let mine = cell;
-                mine.borrow();
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(any(debug_assertions, feature = "safe"), should_panic)]
+    fn it_panics_borrow_iter_mut_ref() {
+        let cell = MaybeAtomicRefCell::new(vec![1, 2]);
+        unsafe {
+            let _first = cell.borrow_iter_mut().next();
+            let _borrow = cell.borrow();
+        }
+    }
 
-                let inner = mine.into_inner();
-                assert_eq!(inner, 6);
-            })
-            .join()
+    #[test]
+    fn borrow_mut_each() {
+        let cell = MaybeAtomicRefCell::new([1, 2, 3]);
+
+        unsafe {
+            let [mut a, b, mut c] = cell.borrow_mut_each();
+            std::thread::scope(|s| {
+                s.spawn(move || *a += 10);
+                s.spawn(move || *c += *b);
+            });
+        }
+
+        assert_eq!(cell.into_inner(), [11, 2, 5]);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(any(debug_assertions, feature = "safe"), should_panic)]
+    fn it_panics_borrow_mut_each_ref() {
+        let cell = MaybeAtomicRefCell::new([1, 2]);
+        unsafe {
+            let [_a, _b] = cell.borrow_mut_each();
+            let _borrow = cell.borrow();
+        }
+    }
+
+    #[test]
+    fn split_at_mut() {
+        let cell = MaybeAtomicRefCell::new(vec![0; 8]);
+
+        unsafe {
+            let guard = MaybeAtomicRefMut::map(cell.borrow_mut(), Vec::as_mut_slice);
+            let (mut left, mut right) = MaybeAtomicRefMut::split_at_mut(guard, 3);
+            std::thread::scope(|s| {
+                s.spawn(move || left.fill(1));
+                s.spawn(move || right.fill(2));
+            });
+        }
+
+        assert_eq!(cell.into_inner(), [1, 1, 1, 2, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn unguarded() {
+        let cell = MaybeAtomicRefCell::new(1);
+
+        unsafe {
+            *cell.get_mut_unchecked() += 1;
+            let value = cell.borrow_unguarded();
+            let guard = cell.borrow();
+            assert_eq!((*value, *guard), (2, 2));
+        }
+    }
+
+    #[test]
+    fn not_borrowed() {
+        let cell = MaybeAtomicRefCell::new(1);
+        cell.debug_assert_not_borrowed();
+        unsafe {
+            *cell.borrow_mut() += 1;
+        }
+        cell.debug_assert_not_borrowed();
+    }
+
+    #[test]
+    #[cfg(all(feature = "borrow_tracking", any(debug_assertions, feature = "safe")))]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    fn it_panics_with_history() {
+        let cell = MaybeAtomicRefCell::new(1);
+        drop(unsafe { cell.borrow() });
+        let line = line!() + 1;
+        let forgotten = unsafe { cell.borrow_mut() };
+        let panic =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe { *cell.borrow() }))
+                .unwrap_err();
+        let message = panic.downcast_ref::<String>().unwrap();
+        let leaked = format!(
+            "borrow_mut of {:#x} at {}:{}:",
+            &cell.inner as *const _ as usize,
+            file!(),
+            line
+        );
+        assert!(
+            message.starts_with("already mutably borrowed"),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains("recent borrows on this thread"),
+            "{}",
+            message
+        );
+        assert!(message.contains(&leaked), "{}", message);
+        assert!(message.contains("release of"), "{}", message);
+        drop(forgotten);
+    }
+
+    #[test]
+    #[cfg(all(feature = "tokio", any(debug_assertions, feature = "safe")))]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    fn it_panics_with_tasks() {
+        static CELL: MaybeAtomicRefCell<u32> = MaybeAtomicRefCell::new(0);
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
             .unwrap();
+        runtime.block_on(async {
+            let holder = tokio::spawn(async {
+                let mut guard = unsafe { CELL.borrow_mut() };
+                tokio::task::yield_now().await;
+                *guard += 1;
+            });
+            let other = tokio::spawn(async { unsafe { *CELL.borrow() } });
+            let panic = other.await.unwrap_err().into_panic();
+            let message = panic.downcast_ref::<String>().unwrap();
+            let expected = format!(
+                "already mutably borrowed by this thread, in task {} (this is task ",
+                holder.id()
+            );
+            assert!(message.starts_with(&expected), "{}", message);
+            holder.await.unwrap();
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "borrow_tracking")]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    fn it_panics_not_quiescent() {
+        let cell = crate::CheckedAtomicRefCell::new(1);
+        let line = line!() + 1;
+        let leftover = cell.borrow_mut();
+        let thread = std::thread::current();
+        let panic = std::panic::catch_unwind(crate::assert_all_quiescent).unwrap_err();
+        let message = panic.downcast_ref::<String>().unwrap();
+        let leftover_borrow = format!(
+            "borrow_mut of {:#x} at {}:{}:",
+            &cell as *const _ as usize,
+            file!(),
+            line
+        );
+        assert!(message.starts_with("cells still borrowed:"), "{}", message);
+        assert!(message.contains(&leftover_borrow), "{}", message);
+        assert!(message.contains(thread.name().unwrap()), "{}", message);
+        drop(leftover);
+    }
+
+    #[test]
+    fn arc_helpers() {
+        use std::sync::Arc;
+
+        let mut arc = Arc::new(MaybeAtomicRefCell::new(vec![1]));
+        MaybeAtomicRefCell::arc_get_mut(&mut arc).unwrap().push(2);
+        let other = Arc::clone(&arc);
+        assert!(MaybeAtomicRefCell::arc_get_mut(&mut arc).is_none());
+        let arc = MaybeAtomicRefCell::try_unwrap_into_inner(arc).unwrap_err();
+        drop(other);
+        assert_eq!(
+            MaybeAtomicRefCell::try_unwrap_into_inner(arc).unwrap(),
+            [1, 2]
+        );
+    }
+
+    #[test]
+    fn raw_access() {
+        let cell = MaybeAtomicRefCell::new(1);
+        unsafe {
+            cell.write_with(|value| *value += 1);
+            let _borrow = cell.borrow();
+            cell.debug_assert_not_mutably_borrowed();
+            assert_eq!(*cell.as_ptr(), 2);
+            assert_eq!(cell.read_with(|value| *value * 2), 4);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(
+        any(debug_assertions, feature = "safe"),
+        should_panic(expected = "still mutably borrowed by this thread")
+    )]
+    fn it_panics_raw_read_borrowed_mut() {
+        let cell = MaybeAtomicRefCell::new(1);
+        unsafe {
+            let _borrow = cell.borrow_mut();
+            cell.debug_assert_not_mutably_borrowed();
+        }
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(any(debug_assertions, feature = "safe"), should_panic)]
+    fn it_panics_write_with_borrowed() {
+        let cell = MaybeAtomicRefCell::new(1);
+        unsafe {
+            let _borrow = cell.borrow();
+            cell.write_with(|value| *value += 1);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(
+        any(debug_assertions, feature = "safe"),
+        should_panic(expected = "still borrowed by this thread")
+    )]
+    fn it_panics_not_borrowed_ref() {
+        let cell = MaybeAtomicRefCell::new(1);
+        unsafe {
+            let _borrow = cell.borrow();
+            cell.debug_assert_not_borrowed();
+        }
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(
+        any(debug_assertions, feature = "safe"),
+        should_panic(expected = "already borrowed by this thread (reentrant borrow)")
+    )]
+    fn it_panics_reentrant() {
+        let cell = MaybeAtomicRefCell::new(5);
+        unsafe {
+            let _borrow1 = MaybeAtomicRef::map(cell.borrow(), |value| value);
+            let _borrow2 = cell.borrow_mut();
+        }
+    }
+
+    #[test]
+    #[cfg(all(
+        any(debug_assertions, feature = "safe"),
+        feature = "atomic_refcell",
+        not(any(feature = "blocking", feature = "abort"))
+    ))]
+    fn it_panics_other_thread() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+        use std::sync::mpsc;
+
+        let cell = &MaybeAtomicRefCell::new(5);
+        std::thread::scope(|scope| {
+            let (borrowed_tx, borrowed_rx) = mpsc::channel();
+            let (done_tx, done_rx) = mpsc::channel::<()>();
+            scope.spawn(move || {
+                let _borrow = unsafe { cell.borrow_mut() };
+                borrowed_tx.send(()).unwrap();
+                let _ = done_rx.recv();
+            });
+            borrowed_rx.recv().unwrap();
+            let message = catch_unwind(AssertUnwindSafe(|| unsafe {
+                let _borrow = cell.borrow();
+            }))
+            .unwrap_err();
+            done_tx.send(()).unwrap();
+            assert_eq!(
+                message.downcast_ref::<String>().unwrap(),
+                "already mutably borrowed by another thread"
+            );
+        });
+
+        // The other thread's borrow is gone, and this thread never held one.
+        unsafe {
+            let _borrow1 = cell.borrow();
+            let _borrow2 = cell.borrow();
         }
     }
 
     #[test]
+    #[cfg(all(
+        any(debug_assertions, feature = "safe"),
+        any(feature = "blocking", not(feature = "atomic_refcell"))
+    ))]
+    fn it_waits_for_other_thread() {
+        use std::sync::mpsc;
+        use std::time::Duration;
+
+        let cell = &MaybeAtomicRefCell::new(5);
+        std::thread::scope(|scope| {
+            let (borrowed_tx, borrowed_rx) = mpsc::channel();
+            scope.spawn(move || {
+                let mut borrow = unsafe { cell.borrow_mut() };
+                borrowed_tx.send(()).unwrap();
+                std::thread::sleep(Duration::from_millis(50));
+                *borrow += 1;
+            });
+            borrowed_rx.recv().unwrap();
+            assert_eq!(*unsafe { cell.borrow() }, 6);
+        });
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
     #[cfg_attr(any(debug_assertions, feature = "safe"), should_panic)]
     fn it_panics_mut_mut() {
         let cell = MaybeAtomicRefCell::new(5);
@@ -276,6 +3120,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
     #[cfg_attr(any(debug_assertions, feature = "safe"), should_panic)]
     fn it_panics_mut_ref() {
         let cell = MaybeAtomicRefCell::new(5);
@@ -286,6 +3131,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
     #[cfg_attr(any(debug_assertions, feature = "safe"), should_panic)]
     fn it_panics_ref_mut() {
         let cell = MaybeAtomicRefCell::new(5);
@@ -0,0 +1,28 @@
+//! The commonly used types and functions of this crate, for glob importing.
+//!
+//! ```
+//! use maybe_atomic_refcell::prelude::*;
+//!
+//! fn first(cells: &[MaybeAtomicRefCell<u32>]) -> MaybeRef<'_, u32> {
+//!     unsafe { cells[0].borrow() }
+//! }
+//!
+//! let cells = [MaybeAtomicRefCell::new(1), MaybeAtomicRefCell::new(2)];
+//! assert_eq!(*first(&cells), 1);
+//! ```
+
+#[cfg(feature = "borrow_tracking")]
+pub use crate::assert_all_quiescent;
+pub use crate::storage::{MaybeAtomicArena, MaybeAtomicRefVec, MaybeAtomicSlab};
+#[cfg(feature = "rayon")]
+pub use crate::ParallelBorrowExt;
+pub use crate::{
+    borrow_all, CheckedAtomicRefCell, Frozen, MaybeAtomicRef, MaybeAtomicRefCell,
+    MaybeAtomicRefMut, MaybeDoubleBuffer, MaybeExclusive, MaybeRef, MaybeRefMut, MaybeTripleBuffer,
+    StaticMaybeAtomicRefCell, TryBorrowExt, UncheckedCell,
+};
+#[cfg(feature = "lock_api")]
+pub use crate::{MaybeCondvar, MaybeMutex, MaybeRwLock};
+
+#[cfg(feature = "derive")]
+pub use crate::MaybeAtomicFields;
@@ -0,0 +1,353 @@
+//! Raw locks for `lock_api`, and a condition variable to go with them, so that code generic over
+//! lock types can use a lock that panics on contention in debug mode, and is as cheap as possible
+//! in release mode. Enabled by the `lock_api` feature.
+
+use crate::flags::BorrowFlag;
+use lock_api::{GuardSend, MutexGuard, RawMutex, RawRwLock};
+use std::fmt;
+use std::sync::{Condvar, Mutex, PoisonError};
+
+/// A `lock_api::RwLock` backed by a `MaybeRawRwLock`.
+pub type MaybeRwLock<T> = lock_api::RwLock<MaybeRawRwLock, T>;
+
+/// A `lock_api::Mutex` backed by a `MaybeRawMutex`.
+pub type MaybeMutex<T> = lock_api::Mutex<MaybeRawMutex, T>;
+
+/// A raw reader-writer lock that, in debug mode (or with the `safe` feature), panics on a
+/// conflicting lock instead of waiting for it, and in release mode spins until it is released.
+///
+/// Either way, it is a real lock, a single atomic borrow flag, so `lock_api`'s safe API is sound
+/// in every build. It is meant for access patterns that never conflict, as validated by debug
+/// builds, so that release builds only pay for an uncontended atomic operation per lock. In
+/// debug mode, conflicting locks from other threads panic rather than wait, even with the
+/// `blocking` feature.
+///
+/// ```
+/// use maybe_atomic_refcell::MaybeRwLock;
+///
+/// let lock = MaybeRwLock::new(vec![1]);
+/// lock.write().push(2);
+/// assert_eq!(*lock.read(), [1, 2]);
+/// ```
+pub struct MaybeRawRwLock {
+    flag: BorrowFlag,
+}
+
+/// A raw mutex that, like `MaybeRawRwLock`, panics on a conflicting lock in debug mode (or with
+/// the `safe` feature), and spins until it is released in release mode.
+pub struct MaybeRawMutex {
+    inner: MaybeRawRwLock,
+}
+
+unsafe impl RawRwLock for MaybeRawRwLock {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: MaybeRawRwLock = MaybeRawRwLock {
+        flag: BorrowFlag::new(),
+    };
+
+    type GuardMarker = GuardSend;
+
+    #[inline]
+    #[track_caller]
+    fn lock_shared(&self) {
+        while !self.try_lock_shared() {
+            contended(format_args!("already locked exclusively"));
+        }
+    }
+
+    #[inline]
+    fn try_lock_shared(&self) -> bool {
+        self.flag.try_borrow().map(std::mem::forget).is_some()
+    }
+
+    #[inline]
+    unsafe fn unlock_shared(&self) {
+        // SAFETY: Locked by `try_lock_shared`, which forgot the borrow.
+        unsafe { self.flag.release_shared() }
+    }
+
+    #[inline]
+    #[track_caller]
+    fn lock_exclusive(&self) {
+        while !self.try_lock_exclusive() {
+            contended(format_args!("already locked"));
+        }
+    }
+
+    #[inline]
+    fn try_lock_exclusive(&self) -> bool {
+        self.flag.try_borrow_mut().map(std::mem::forget).is_some()
+    }
+
+    #[inline]
+    unsafe fn unlock_exclusive(&self) {
+        // SAFETY: Locked by `try_lock_exclusive`, which forgot the borrow.
+        unsafe { self.flag.release_exclusive() }
+    }
+}
+
+/// Called when a lock is contended, before trying again: reports a violation in debug mode (or
+/// with the `safe` feature), and lets other threads run in release mode.
+#[cold]
+#[track_caller]
+fn contended(message: fmt::Arguments) {
+    #[cfg(any(debug_assertions, feature = "safe"))]
+    crate::fixed::violation(message);
+    #[cfg(not(any(debug_assertions, feature = "safe")))]
+    {
+        let _ = message;
+        std::thread::yield_now();
+    }
+}
+
+unsafe impl RawMutex for MaybeRawMutex {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: MaybeRawMutex = MaybeRawMutex {
+        inner: MaybeRawRwLock::INIT,
+    };
+
+    type GuardMarker = GuardSend;
+
+    #[inline]
+    #[track_caller]
+    fn lock(&self) {
+        self.inner.lock_exclusive();
+    }
+
+    #[inline]
+    fn try_lock(&self) -> bool {
+        self.inner.try_lock_exclusive()
+    }
+
+    #[inline]
+    unsafe fn unlock(&self) {
+        unsafe { self.inner.unlock_exclusive() }
+    }
+}
+
+/// A condition variable for `MaybeMutex`, which really waits for a notification in every build.
+///
+/// Waking up relocks the mutex by retrying rather than panicking, since the notifying thread
+/// usually still holds it. In debug mode (or with the `safe` feature), waiting with different
+/// mutexes at the same time panics.
+///
+/// ```
+/// use maybe_atomic_refcell::{MaybeCondvar, MaybeMutex};
+///
+/// let ready = MaybeMutex::new(true);
+/// let condvar = MaybeCondvar::new();
+/// let mut guard = ready.lock();
+/// while !*guard {
+///     condvar.wait(&mut guard);
+/// }
+/// condvar.notify_all();
+/// ```
+pub struct MaybeCondvar {
+    /// The address of the mutex being waited with, and the number of threads waiting.
+    waiters: Mutex<(usize, usize)>,
+    condvar: Condvar,
+}
+
+impl MaybeCondvar {
+    /// Creates a new `MaybeCondvar`.
+    #[inline]
+    pub const fn new() -> MaybeCondvar {
+        MaybeCondvar {
+            waiters: Mutex::new((0, 0)),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Unlocks the mutex of `guard`, waits for a notification, and locks it again. Like any
+    /// condition variable, it may also wake up spuriously, so it should be called in a loop.
+    ///
+    /// # Panics
+    ///
+    /// Panics if other threads are waiting with a different mutex, in debug mode (or with the
+    /// `safe` feature).
+    #[inline]
+    #[track_caller]
+    pub fn wait<T: ?Sized>(&self, guard: &mut MutexGuard<'_, MaybeRawMutex, T>) {
+        let mutex = MutexGuard::mutex(guard);
+        let address = crate::reentrancy::address(mutex);
+        let mut waiters = self.lock();
+        #[cfg(any(debug_assertions, feature = "safe"))]
+        if waiters.1 != 0 && waiters.0 != address {
+            drop(waiters);
+            crate::fixed::violation(format_args!(
+                "MaybeCondvar waited on with different mutexes"
+            ));
+        }
+        *waiters = (address, waiters.1 + 1);
+        // SAFETY: Locked by `guard`, which can't be used until it is locked again below.
+        unsafe { mutex.force_unlock() };
+        let mut waiters = self
+            .condvar
+            .wait(waiters)
+            .unwrap_or_else(PoisonError::into_inner);
+        waiters.1 -= 1;
+        drop(waiters);
+        // SAFETY: Only used to lock the mutex on behalf of `guard`.
+        while !unsafe { mutex.raw() }.try_lock() {
+            std::thread::yield_now();
+        }
+    }
+
+    /// Wakes up one thread waiting on this condition variable, if any.
+    #[inline]
+    pub fn notify_one(&self) {
+        // Locking first, so that the notification can't be missed by a thread about to wait.
+        drop(self.lock());
+        self.condvar.notify_one();
+    }
+
+    /// Wakes up all threads waiting on this condition variable.
+    #[inline]
+    pub fn notify_all(&self) {
+        drop(self.lock());
+        self.condvar.notify_all();
+    }
+
+    // The mutex is never held while running user code, so poisoning is impossible in practice.
+    #[inline]
+    fn lock(&self) -> std::sync::MutexGuard<'_, (usize, usize)> {
+        self.waiters.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for MaybeCondvar {
+    #[inline]
+    fn default() -> MaybeCondvar {
+        MaybeCondvar::new()
+    }
+}
+
+impl fmt::Debug for MaybeCondvar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MaybeCondvar").finish_non_exhaustive()
+    }
+}
+
+impl fmt::Debug for MaybeRawRwLock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MaybeRawRwLock").finish_non_exhaustive()
+    }
+}
+
+impl fmt::Debug for MaybeRawMutex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MaybeRawMutex").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{MaybeCondvar, MaybeMutex, MaybeRwLock};
+
+    #[test]
+    fn it_works() {
+        let lock = MaybeRwLock::new(1);
+        {
+            let a = lock.read();
+            let b = lock.read();
+            assert_eq!(*a + *b, 2);
+        }
+        *lock.write() += 1;
+        assert_eq!(lock.try_write().map(|guard| *guard), Some(2));
+
+        let mutex = MaybeMutex::new(String::from("a"));
+        mutex.lock().push('b');
+        assert_eq!(mutex.into_inner(), "ab");
+
+        let condvar = MaybeCondvar::default();
+        condvar.notify_all();
+        assert_eq!(format!("{:?}", condvar), "MaybeCondvar { .. }");
+    }
+
+    #[test]
+    fn try_lock() {
+        let lock = MaybeRwLock::new(1);
+        let guard = lock.read();
+        assert!(lock.try_write().is_none());
+        assert!(lock.is_locked());
+        drop(guard);
+        assert!(!lock.is_locked());
+
+        let mutex = MaybeMutex::new(1);
+        let guard = mutex.lock();
+        assert!(mutex.try_lock().is_none());
+        drop(guard);
+        assert!(mutex.try_lock().is_some());
+    }
+
+    #[test]
+    #[cfg(not(any(debug_assertions, feature = "safe")))]
+    fn contended() {
+        let mutex = MaybeMutex::new(0);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *mutex.lock() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(mutex.into_inner(), 4000);
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, feature = "safe"))]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[should_panic(expected = "already locked exclusively")]
+    fn it_panics_write_read() {
+        let lock = MaybeRwLock::new(1);
+        let _write = lock.write();
+        let _read = lock.read();
+    }
+
+    #[test]
+    fn condvar() {
+        let ready = MaybeMutex::new(false);
+        let condvar = MaybeCondvar::new();
+        // Locked before the other thread starts, so that it is the one to wait for the mutex.
+        let mut guard = ready.lock();
+        std::thread::scope(|s| {
+            s.spawn(|| loop {
+                // The waiting thread may not have unlocked the mutex yet.
+                if let Some(mut guard) = ready.try_lock() {
+                    *guard = true;
+                    condvar.notify_one();
+                    break;
+                }
+                std::thread::yield_now();
+            });
+            while !*guard {
+                condvar.wait(&mut guard);
+            }
+        });
+        drop(guard);
+        assert!(ready.into_inner());
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, feature = "safe"))]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[should_panic(expected = "MaybeCondvar waited on with different mutexes")]
+    fn it_panics_different_mutexes() {
+        use std::sync::Arc;
+
+        let shared = Arc::new((MaybeMutex::new(()), MaybeCondvar::new()));
+        let other = Arc::clone(&shared);
+        // Never notified, so left waiting.
+        std::thread::spawn(move || {
+            let (mutex, condvar) = &*other;
+            condvar.wait(&mut mutex.lock());
+        });
+        let (_, condvar) = &*shared;
+        while condvar.lock().1 == 0 {
+            std::thread::yield_now();
+        }
+        condvar.wait(&mut MaybeMutex::new(()).lock());
+    }
+}
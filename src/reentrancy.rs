@@ -0,0 +1,417 @@
+//! Tracks which cells each thread currently has borrowed, so that a conflicting borrow can say
+//! whether it is reentrant or comes from another thread. Each guard keeps a handle to the list
+//! of the thread that made it, so that it is released from that list wherever it is dropped.
+//!
+//! With the `borrow_tracking` feature, each thread also keeps the cells it recently borrowed and
+//! released, so that a conflict can show how it came about, and its list is registered, so that
+//! `assert_all_quiescent` can find leftover borrows.
+
+#[cfg(feature = "borrow_tracking")]
+use crate::fixed;
+#[cfg(feature = "borrow_tracking")]
+use std::cell::RefCell;
+#[cfg(feature = "borrow_tracking")]
+use std::collections::VecDeque;
+use std::fmt;
+use std::panic::Location;
+use std::sync::Arc;
+#[cfg(feature = "borrow_tracking")]
+use std::sync::Weak;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+#[cfg(feature = "borrow_tracking")]
+use std::thread::{self, ThreadId};
+
+/// The number of borrow events kept per thread.
+#[cfg(feature = "borrow_tracking")]
+const HISTORY: usize = 16;
+
+/// The borrows outstanding on a thread, once per guard, shared with the guards (and the
+/// registry).
+type Records = Mutex<Vec<Record>>;
+
+/// An outstanding borrow of a cell.
+#[derive(Clone, Copy)]
+struct Record {
+    cell: Id,
+    #[cfg(feature = "borrow_tracking")]
+    mutable: bool,
+    #[cfg(feature = "borrow_tracking")]
+    location: &'static Location<'static>,
+}
+
+/// The `Records` of a thread, in the registry.
+#[cfg(feature = "borrow_tracking")]
+struct Registered {
+    thread: ThreadId,
+    name: Option<String>,
+    records: Weak<Records>,
+}
+
+/// Every thread that has borrowed a cell, while it is alive.
+#[cfg(feature = "borrow_tracking")]
+static REGISTRY: Mutex<Vec<Registered>> = Mutex::new(Vec::new());
+
+thread_local! {
+    /// The borrows made by this thread.
+    #[cfg(not(feature = "borrow_tracking"))]
+    static HELD: Arc<Records> = Arc::default();
+    /// The borrows made by this thread.
+    #[cfg(feature = "borrow_tracking")]
+    static HELD: Arc<Records> = register();
+
+    /// The last `HISTORY` borrow events on this thread, oldest first.
+    #[cfg(feature = "borrow_tracking")]
+    static EVENTS: RefCell<VecDeque<Event>> = const { RefCell::new(VecDeque::new()) };
+}
+
+/// The cells borrowed inside `tokio` tasks, once per outstanding guard, with the task that
+/// borrowed them.
+#[cfg(feature = "tokio")]
+static TASKS: Mutex<Vec<(Id, tokio::task::Id)>> = Mutex::new(Vec::new());
+
+// The mutexes are never held while running user code, so poisoning is impossible in practice.
+#[inline]
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(feature = "tokio")]
+#[inline]
+fn tasks() -> MutexGuard<'static, Vec<(Id, tokio::task::Id)>> {
+    lock(&TASKS)
+}
+
+/// Adds the current thread to the registry, dropping threads that have exited.
+#[cfg(feature = "borrow_tracking")]
+#[cold]
+fn register() -> Arc<Records> {
+    let records = Arc::default();
+    let thread = thread::current();
+    let mut registry = lock(&REGISTRY);
+    registry.retain(|registered| registered.records.strong_count() > 0);
+    registry.push(Registered {
+        thread: thread.id(),
+        name: thread.name().map(String::from),
+        records: Arc::downgrade(&records),
+    });
+    records
+}
+
+/// A borrow of a cell being acquired or released.
+#[cfg(feature = "borrow_tracking")]
+#[derive(Clone, Copy)]
+struct Event {
+    kind: Kind,
+    cell: Id,
+    /// Where the borrow was acquired.
+    location: &'static Location<'static>,
+}
+
+#[cfg(feature = "borrow_tracking")]
+#[derive(Clone, Copy)]
+enum Kind {
+    Borrow,
+    BorrowMut,
+    Release,
+}
+
+/// Records a borrow of a cell by the current thread for as long as it is alive.
+pub(crate) struct Held {
+    cell: Id,
+    /// The borrows of the thread that made this one, unless it was exiting.
+    records: Option<Arc<Records>>,
+    mutable: bool,
+    location: &'static Location<'static>,
+    #[cfg(feature = "tokio")]
+    task: Option<tokio::task::Id>,
+}
+
+impl Held {
+    #[inline]
+    pub(crate) fn new(cell: Id, mutable: bool, location: &'static Location<'static>) -> Held {
+        let records = HELD.try_with(Arc::clone).ok();
+        if let Some(records) = &records {
+            lock(records).push(Record {
+                cell,
+                #[cfg(feature = "borrow_tracking")]
+                mutable,
+                #[cfg(feature = "borrow_tracking")]
+                location,
+            });
+        }
+        #[cfg(feature = "tokio")]
+        let task = tokio::task::try_id();
+        #[cfg(feature = "tokio")]
+        if let Some(task) = task {
+            tasks().push((cell, task));
+        }
+        #[cfg(feature = "borrow_tracking")]
+        record(Event {
+            kind: if mutable {
+                Kind::BorrowMut
+            } else {
+                Kind::Borrow
+            },
+            cell,
+            location,
+        });
+        Held {
+            cell,
+            records,
+            mutable,
+            location,
+            #[cfg(feature = "tokio")]
+            task,
+        }
+    }
+}
+
+impl Clone for Held {
+    #[inline]
+    fn clone(&self) -> Held {
+        Held::new(self.cell, self.mutable, self.location)
+    }
+}
+
+impl Drop for Held {
+    #[inline]
+    fn drop(&mut self) {
+        #[cfg(feature = "borrow_tracking")]
+        record(Event {
+            kind: Kind::Release,
+            cell: self.cell,
+            location: self.location,
+        });
+        #[cfg(feature = "tokio")]
+        if let Some(task) = self.task {
+            let mut tasks = tasks();
+            if let Some(i) = tasks.iter().rposition(|&held| held == (self.cell, task)) {
+                tasks.swap_remove(i);
+            }
+        }
+        // Released from the records of the thread that made the borrow, even if the guard was
+        // sent to this one.
+        if let Some(records) = &self.records {
+            let mut held = lock(records);
+            if let Some(i) = held.iter().rposition(|record| record.cell == self.cell) {
+                held.swap_remove(i);
+            }
+        }
+    }
+}
+
+/// Returns whether the current thread holds a borrow of `cell`.
+#[inline]
+pub(crate) fn holds(cell: Id) -> bool {
+    HELD.try_with(|held| lock(held).iter().any(|record| record.cell == cell))
+        .unwrap_or(false)
+}
+
+/// Describes who holds the borrow that conflicts with a new borrow of `cell`.
+#[cold]
+pub(crate) fn culprit(cell: Id) -> Culprit {
+    Culprit {
+        reentrant: holds(cell),
+        #[cfg(feature = "tokio")]
+        tasks: tasks()
+            .iter()
+            .filter(|&&(held, _)| held == cell)
+            .map(|&(_, task)| task)
+            .collect(),
+    }
+}
+
+/// Who holds a conflicting borrow: this thread or another, and with the `tokio` feature, which
+/// tasks (if it was borrowed inside any).
+pub(crate) struct Culprit {
+    reentrant: bool,
+    #[cfg(feature = "tokio")]
+    tasks: Vec<tokio::task::Id>,
+}
+
+impl fmt::Display for Culprit {
+    #[cold]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        #[cfg(feature = "tokio")]
+        let current = tokio::task::try_id();
+        // Another task on the same thread, which suspended while holding its borrow.
+        #[cfg(feature = "tokio")]
+        let suspended =
+            current.is_some_and(|task| !self.tasks.is_empty() && !self.tasks.contains(&task));
+        #[cfg(not(feature = "tokio"))]
+        let suspended = false;
+        f.write_str(match (self.reentrant, suspended) {
+            (true, false) => "by this thread (reentrant borrow)",
+            (true, true) => "by this thread",
+            (false, _) => "by another thread",
+        })?;
+        #[cfg(feature = "tokio")]
+        {
+            for (i, task) in self.tasks.iter().enumerate() {
+                let separator = if i == 0 { ", in task " } else { " and " };
+                write!(f, "{}{}", separator, task)?;
+            }
+            if let Some(task) = current {
+                write!(f, " (this is task {})", task)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "borrow_tracking")]
+#[inline]
+fn record(event: Event) {
+    let _ = EVENTS.try_with(|events| {
+        let mut events = events.borrow_mut();
+        if events.len() == HISTORY {
+            events.pop_front();
+        }
+        events.push_back(event);
+    });
+}
+
+/// The recent borrow events on the current thread, for appending to a violation's message.
+/// Empty without the `borrow_tracking` feature.
+pub(crate) struct History;
+
+impl fmt::Display for History {
+    #[cold]
+    #[cfg(not(feature = "borrow_tracking"))]
+    fn fmt(&self, _: &mut fmt::Formatter) -> fmt::Result {
+        Ok(())
+    }
+
+    #[cold]
+    #[cfg(feature = "borrow_tracking")]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let events = EVENTS
+            .try_with(|events| events.borrow().clone())
+            .unwrap_or_default();
+        if events.is_empty() {
+            return Ok(());
+        }
+        f.write_str("\nrecent borrows on this thread, oldest first:")?;
+        for event in events {
+            match event.kind {
+                Kind::Borrow => write!(
+                    f,
+                    "\n  borrow of {:#x} at {}",
+                    event.cell.address, event.location
+                ),
+                Kind::BorrowMut => write!(
+                    f,
+                    "\n  borrow_mut of {:#x} at {}",
+                    event.cell.address, event.location
+                ),
+                Kind::Release => write!(
+                    f,
+                    "\n  release of {:#x} (borrowed at {})",
+                    event.cell.address, event.location
+                ),
+            }?;
+        }
+        Ok(())
+    }
+}
+
+/// Panics if any cell in the process is still borrowed, listing the outstanding borrows, with
+/// where and on which thread each was made. Requires the `borrow_tracking` feature.
+///
+/// This is for the end of each frame or tick, when every guard should have been dropped, so that
+/// a leaked guard is reported there rather than by the next conflicting borrow. Only checked
+/// borrows are tracked, so in release mode (without the `safe` feature), only borrows of
+/// `CheckedAtomicRefCell`s are found.
+///
+/// ```
+/// use maybe_atomic_refcell::{assert_all_quiescent, MaybeAtomicRefCell};
+///
+/// let cell = MaybeAtomicRefCell::new(0);
+/// for _ in 0..3 {
+///     unsafe {
+///         *cell.borrow_mut() += 1;
+///     }
+///     assert_all_quiescent();
+/// }
+/// ```
+#[cfg(feature = "borrow_tracking")]
+#[track_caller]
+pub fn assert_all_quiescent() {
+    let outstanding = lock(&REGISTRY)
+        .iter()
+        .filter_map(|registered| {
+            let records = registered.records.upgrade()?;
+            let records = lock(&records).clone();
+            Some(records.into_iter().map(move |record| {
+                let thread = match &registered.name {
+                    Some(name) => format!("'{}'", name),
+                    None => format!("{:?}", registered.thread),
+                };
+                (record, thread)
+            }))
+        })
+        .flatten()
+        .collect::<Vec<_>>();
+    if outstanding.is_empty() {
+        return;
+    }
+    let mut list = String::new();
+    for (record, thread) in &outstanding {
+        list.push_str(&format!(
+            "\n  {} of {:#x} at {} on thread {}",
+            if record.mutable {
+                "borrow_mut"
+            } else {
+                "borrow"
+            },
+            record.cell.address,
+            record.location,
+            thread
+        ));
+    }
+    fixed::violation(format_args!("cells still borrowed:{}", list));
+}
+
+/// Identifies the cell at `cell`.
+#[inline]
+pub(crate) fn address<T: ?Sized>(cell: *const T) -> usize {
+    cell as *const () as usize
+}
+
+/// Identifies a cell by its address, size, and type, so that a cell at the start of another
+/// cell's value isn't mistaken for it, even if it is the same size (like the inner cell of a
+/// `MaybeAtomicRefCell<MaybeAtomicRefCell<T>>` with the `side_table` feature) or zero-sized.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Id {
+    pub(crate) address: usize,
+    size: usize,
+    type_name: &'static str,
+}
+
+/// Identifies `cell`, for tracking its borrows.
+#[inline]
+pub(crate) fn id<T: ?Sized>(cell: &T) -> Id {
+    Id {
+        address: address(cell),
+        size: std::mem::size_of_val(cell),
+        type_name: std::any::type_name::<T>(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{holds, id};
+    use crate::backend::BorrowCell;
+    use crate::fixed;
+
+    #[test]
+    fn released_on_another_thread() {
+        let cell = BorrowCell::new(1);
+        let guard = fixed::borrow_mut(&cell);
+        assert!(holds(id(&cell)));
+        std::thread::scope(|s| {
+            s.spawn(move || drop(guard));
+        });
+        assert!(!holds(id(&cell)));
+    }
+}
@@ -0,0 +1,119 @@
+//! A `MaybeAtomicRefCell` for `static` items, in place of `static mut`.
+
+use crate::MaybeAtomicRefCell;
+use std::fmt;
+use std::fmt::Debug;
+use std::ops::Deref;
+use std::sync::OnceLock;
+
+/// A `MaybeAtomicRefCell<T>` that can be a `static` item, optionally initialized on first use.
+///
+/// It dereferences to the `MaybeAtomicRefCell<T>`, so access is checked exactly when
+/// `MaybeAtomicRefCell` is checked.
+///
+/// ```
+/// use maybe_atomic_refcell::StaticMaybeAtomicRefCell;
+///
+/// static COUNTER: StaticMaybeAtomicRefCell<u32> = StaticMaybeAtomicRefCell::new(0);
+/// static NAMES: StaticMaybeAtomicRefCell<Vec<String>> = StaticMaybeAtomicRefCell::new_lazy(|| {
+///     vec!["a".to_owned()]
+/// });
+///
+/// unsafe {
+///     *COUNTER.borrow_mut() += 1;
+///     NAMES.borrow_mut().push("b".to_owned());
+///     assert_eq!(NAMES.borrow().len(), 2);
+/// }
+/// ```
+pub struct StaticMaybeAtomicRefCell<T> {
+    init: Init<T>,
+}
+
+enum Init<T> {
+    Value(MaybeAtomicRefCell<T>),
+    Lazy(OnceLock<MaybeAtomicRefCell<T>>, fn() -> T),
+}
+
+impl<T> StaticMaybeAtomicRefCell<T> {
+    /// Creates a new `StaticMaybeAtomicRefCell` containing `value`.
+    #[inline]
+    pub const fn new(value: T) -> StaticMaybeAtomicRefCell<T> {
+        StaticMaybeAtomicRefCell {
+            init: Init::Value(MaybeAtomicRefCell::new(value)),
+        }
+    }
+
+    /// Creates a new `StaticMaybeAtomicRefCell` that calls `init` for its value on first use.
+    ///
+    /// If several threads use it at once, one of them calls `init` and the rest wait.
+    #[inline]
+    pub const fn new_lazy(init: fn() -> T) -> StaticMaybeAtomicRefCell<T> {
+        StaticMaybeAtomicRefCell {
+            init: Init::Lazy(OnceLock::new(), init),
+        }
+    }
+
+    /// Returns the cell, if it has been initialized.
+    #[inline]
+    fn get(&self) -> Option<&MaybeAtomicRefCell<T>> {
+        match &self.init {
+            Init::Value(cell) => Some(cell),
+            Init::Lazy(cell, _) => cell.get(),
+        }
+    }
+}
+
+impl<T> Deref for StaticMaybeAtomicRefCell<T> {
+    type Target = MaybeAtomicRefCell<T>;
+
+    #[inline]
+    fn deref(&self) -> &MaybeAtomicRefCell<T> {
+        match &self.init {
+            Init::Value(cell) => cell,
+            Init::Lazy(cell, init) => cell.get_or_init(|| MaybeAtomicRefCell::new(init())),
+        }
+    }
+}
+
+impl<T: Debug> Debug for StaticMaybeAtomicRefCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.get() {
+            Some(cell) => f
+                .debug_tuple("StaticMaybeAtomicRefCell")
+                .field(cell)
+                .finish(),
+            None => f.write_str("StaticMaybeAtomicRefCell(<uninit>)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::StaticMaybeAtomicRefCell;
+
+    #[test]
+    fn it_works() {
+        static VALUE: StaticMaybeAtomicRefCell<u32> = StaticMaybeAtomicRefCell::new(1);
+        static LAZY: StaticMaybeAtomicRefCell<Vec<u32>> =
+            StaticMaybeAtomicRefCell::new_lazy(|| vec![1]);
+
+        assert_eq!(format!("{:?}", LAZY), "StaticMaybeAtomicRefCell(<uninit>)");
+        unsafe {
+            *VALUE.borrow_mut() += 1;
+            LAZY.borrow_mut().push(2);
+            assert_eq!(*VALUE.borrow(), 2);
+            assert_eq!(*LAZY.borrow(), [1, 2]);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(any(debug_assertions, feature = "safe"), should_panic)]
+    fn it_panics_mut_ref() {
+        static VALUE: StaticMaybeAtomicRefCell<u32> = StaticMaybeAtomicRefCell::new(1);
+        unsafe {
+            let _borrow1 = VALUE.borrow_mut();
+            let _borrow2 = VALUE.borrow();
+        }
+    }
+}
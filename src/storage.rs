@@ -0,0 +1,676 @@
+//! Storage types built out of `MaybeAtomicRefCell`s.
+
+#[cfg(any(debug_assertions, feature = "safe"))]
+use crate::flags::BorrowFlag;
+use crate::{fixed, MaybeAtomicRef, MaybeAtomicRefCell, MaybeAtomicRefMut};
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::fmt::Debug;
+
+/// Contiguous storage where each occupied slot is an independent `MaybeAtomicRefCell<T>`.
+///
+/// Keys are stable: a key returned by `insert` refers to the same value until it is removed,
+/// after which it may be reused by a later `insert`.
+pub struct MaybeAtomicSlab<T> {
+    slots: Vec<Slot<T>>,
+    /// Head of the linked list of vacant slots, or `slots.len()` if there are none.
+    next_vacant: usize,
+    len: usize,
+}
+
+enum Slot<T> {
+    Occupied(MaybeAtomicRefCell<T>),
+    Vacant { next_vacant: usize },
+}
+
+impl<T> MaybeAtomicSlab<T> {
+    /// Creates a new, empty `MaybeAtomicSlab`.
+    #[inline]
+    pub const fn new() -> MaybeAtomicSlab<T> {
+        MaybeAtomicSlab {
+            slots: Vec::new(),
+            next_vacant: 0,
+            len: 0,
+        }
+    }
+
+    /// Creates a new, empty `MaybeAtomicSlab` with room for `capacity` values.
+    #[inline]
+    pub fn with_capacity(capacity: usize) -> MaybeAtomicSlab<T> {
+        MaybeAtomicSlab {
+            slots: Vec::with_capacity(capacity),
+            next_vacant: 0,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of values in the slab.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the slab contains no values.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Inserts `value` into a vacant slot, returning its key.
+    pub fn insert(&mut self, value: T) -> usize {
+        let key = self.next_vacant;
+        let cell = Slot::Occupied(MaybeAtomicRefCell::new(value));
+        if let Some(slot) = self.slots.get_mut(key) {
+            match std::mem::replace(slot, cell) {
+                Slot::Vacant { next_vacant } => self.next_vacant = next_vacant,
+                Slot::Occupied(_) => unreachable!("vacant list points to an occupied slot"),
+            }
+        } else {
+            self.slots.push(cell);
+            self.next_vacant = self.slots.len();
+        }
+        self.len += 1;
+        key
+    }
+
+    /// Removes the value at `key`, if any, and returns it.
+    pub fn remove(&mut self, key: usize) -> Option<T> {
+        let slot = self.slots.get_mut(key)?;
+        if let Slot::Vacant { .. } = slot {
+            return None;
+        }
+        let vacant = Slot::Vacant {
+            next_vacant: self.next_vacant,
+        };
+        match std::mem::replace(slot, vacant) {
+            Slot::Occupied(cell) => {
+                self.next_vacant = key;
+                self.len -= 1;
+                Some(cell.into_inner())
+            }
+            Slot::Vacant { .. } => unreachable!(),
+        }
+    }
+
+    /// Returns `true` if there is a value at `key`.
+    #[inline]
+    pub fn contains(&self, key: usize) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns the cell at `key`, if any.
+    #[inline]
+    pub fn get(&self, key: usize) -> Option<&MaybeAtomicRefCell<T>> {
+        match self.slots.get(key)? {
+            Slot::Occupied(cell) => Some(cell),
+            Slot::Vacant { .. } => None,
+        }
+    }
+
+    /// Returns a mutable reference to the value at `key`, if any.
+    ///
+    /// No runtime checks take place (unless debug assertions are enabled)
+    /// because this call borrows `MaybeAtomicSlab` mutably at compile-time.
+    #[inline]
+    pub fn get_mut(&mut self, key: usize) -> Option<&mut T> {
+        match self.slots.get_mut(key)? {
+            Slot::Occupied(cell) => Some(cell.get_mut()),
+            Slot::Vacant { .. } => None,
+        }
+    }
+
+    /// Iterates over the keys and cells of the values in the slab, in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &MaybeAtomicRefCell<T>)> + '_ {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(key, slot)| match slot {
+                Slot::Occupied(cell) => Some((key, cell)),
+                Slot::Vacant { .. } => None,
+            })
+    }
+
+    /// Removes all values from the slab.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.next_vacant = 0;
+        self.len = 0;
+    }
+}
+
+impl<T> Default for MaybeAtomicSlab<T> {
+    #[inline]
+    fn default() -> MaybeAtomicSlab<T> {
+        MaybeAtomicSlab::new()
+    }
+}
+
+impl<T: Debug> Debug for MaybeAtomicSlab<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// Contiguous storage where each value is borrowed independently, like a `Vec` of
+/// `MaybeAtomicRefCell<T>`s, except that the borrows are tracked in a separate table of compact
+/// flags rather than next to each value.
+///
+/// With runtime checks, each value adds a 4-byte flag to the table, and values stay as densely
+/// packed as in a `Vec<T>`. Without them, there is no table at all. Unlike the `blocking`
+/// backend, conflicting borrows from another thread panic rather than wait.
+pub struct MaybeAtomicArena<T> {
+    values: Vec<UnsafeCell<T>>,
+    #[cfg(any(debug_assertions, feature = "safe"))]
+    flags: Vec<BorrowFlag>,
+}
+
+impl<T> MaybeAtomicArena<T> {
+    /// Creates a new, empty `MaybeAtomicArena`.
+    #[inline]
+    pub const fn new() -> MaybeAtomicArena<T> {
+        MaybeAtomicArena {
+            values: Vec::new(),
+            #[cfg(any(debug_assertions, feature = "safe"))]
+            flags: Vec::new(),
+        }
+    }
+
+    /// Creates a new, empty `MaybeAtomicArena` with room for `capacity` values.
+    #[inline]
+    pub fn with_capacity(capacity: usize) -> MaybeAtomicArena<T> {
+        MaybeAtomicArena {
+            values: Vec::with_capacity(capacity),
+            #[cfg(any(debug_assertions, feature = "safe"))]
+            flags: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the number of values in the arena.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns `true` if the arena contains no values.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Appends `value` to the arena, returning its index.
+    #[inline]
+    pub fn push(&mut self, value: T) -> usize {
+        self.values.push(UnsafeCell::new(value));
+        #[cfg(any(debug_assertions, feature = "safe"))]
+        self.flags.push(BorrowFlag::new());
+        self.values.len() - 1
+    }
+
+    /// Immutably borrows the value at `index`. Performs runtime checks in debug mode, but not
+    /// in release mode (hence `unsafe`).
+    ///
+    /// # Safety
+    ///
+    /// The value must not be mutably borrowed for the lifetime of the returned guard.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    #[inline]
+    #[track_caller]
+    pub unsafe fn borrow(&self, index: usize) -> MaybeAtomicRef<'_, T> {
+        let value = self.values[index].get();
+        #[cfg(any(debug_assertions, feature = "safe"))]
+        return unsafe { fixed::borrow_flagged(&self.flags[index], value) };
+        #[cfg(not(any(debug_assertions, feature = "safe")))]
+        unsafe {
+            fixed::borrow_unchecked(value)
+        }
+    }
+
+    /// Mutably borrows the value at `index`. Performs runtime checks in debug mode, but not in
+    /// release mode (hence `unsafe`).
+    ///
+    /// # Safety
+    ///
+    /// The value must not be borrowed at all for the lifetime of the returned guard.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    #[inline]
+    #[track_caller]
+    pub unsafe fn borrow_mut(&self, index: usize) -> MaybeAtomicRefMut<'_, T> {
+        let value = self.values[index].get();
+        #[cfg(any(debug_assertions, feature = "safe"))]
+        return unsafe { fixed::borrow_mut_flagged(&self.flags[index], value) };
+        #[cfg(not(any(debug_assertions, feature = "safe")))]
+        unsafe {
+            fixed::borrow_mut_unchecked(value)
+        }
+    }
+
+    /// Returns a mutable reference to the value at `index`, if any.
+    ///
+    /// No runtime checks take place (unless debug assertions are enabled)
+    /// because this call borrows `MaybeAtomicArena` mutably at compile-time.
+    #[inline]
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.values.get_mut(index).map(UnsafeCell::get_mut)
+    }
+
+    /// Consumes the `MaybeAtomicArena`, returning the values in index order.
+    #[inline]
+    pub fn into_vec(self) -> Vec<T> {
+        from_cells(self.values)
+    }
+
+    /// Removes all values from the arena.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.values.clear();
+        #[cfg(any(debug_assertions, feature = "safe"))]
+        self.flags.clear();
+    }
+}
+
+unsafe impl<T: Send> Send for MaybeAtomicArena<T> {}
+unsafe impl<T: Send + Sync> Sync for MaybeAtomicArena<T> {}
+
+impl<T> Default for MaybeAtomicArena<T> {
+    #[inline]
+    fn default() -> MaybeAtomicArena<T> {
+        MaybeAtomicArena::new()
+    }
+}
+
+impl<T> FromIterator<T> for MaybeAtomicArena<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> MaybeAtomicArena<T> {
+        let values: Vec<_> = iter.into_iter().map(UnsafeCell::new).collect();
+        MaybeAtomicArena {
+            #[cfg(any(debug_assertions, feature = "safe"))]
+            flags: values.iter().map(|_| BorrowFlag::new()).collect(),
+            values,
+        }
+    }
+}
+
+impl<T> From<Vec<T>> for MaybeAtomicArena<T> {
+    fn from(values: Vec<T>) -> MaybeAtomicArena<T> {
+        MaybeAtomicArena {
+            #[cfg(any(debug_assertions, feature = "safe"))]
+            flags: values.iter().map(|_| BorrowFlag::new()).collect(),
+            values: into_cells(values),
+        }
+    }
+}
+
+impl<T: Debug> Debug for MaybeAtomicArena<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut debug = f.debug_list();
+        #[cfg(any(debug_assertions, feature = "safe"))]
+        for (value, flag) in self.values.iter().zip(&self.flags) {
+            match flag.try_borrow() {
+                // SAFETY: The value isn't mutably borrowed while the flag is borrowed.
+                Some(_borrow) => debug.entry(unsafe { &*value.get() }),
+                None => debug.entry(&format_args!("<mutably borrowed>")),
+            };
+        }
+        #[cfg(not(any(debug_assertions, feature = "safe")))]
+        debug.entries(self.values.iter().map(|_| format_args!("<unchecked>")));
+        debug.finish()
+    }
+}
+
+/// A `Vec<T>` whose elements are borrowed independently, so that distinct indices can be
+/// mutably borrowed at the same time. The elements stay in a single contiguous buffer, with their
+/// borrows tracked like in a `MaybeAtomicArena`.
+///
+/// ```
+/// use maybe_atomic_refcell::storage::MaybeAtomicRefVec;
+///
+/// let vec = MaybeAtomicRefVec::from(vec![1, 2, 3]);
+/// unsafe {
+///     let mut first = vec.borrow_index_mut(0);
+///     let mut last = vec.borrow_index_mut(2);
+///     std::mem::swap(&mut *first, &mut *last);
+/// }
+/// assert_eq!(vec.into_inner(), [3, 2, 1]);
+/// ```
+pub struct MaybeAtomicRefVec<T> {
+    arena: MaybeAtomicArena<T>,
+}
+
+impl<T> MaybeAtomicRefVec<T> {
+    /// Creates a new, empty `MaybeAtomicRefVec`.
+    #[inline]
+    pub const fn new() -> MaybeAtomicRefVec<T> {
+        MaybeAtomicRefVec {
+            arena: MaybeAtomicArena::new(),
+        }
+    }
+
+    /// Creates a new, empty `MaybeAtomicRefVec` with room for `capacity` elements.
+    #[inline]
+    pub fn with_capacity(capacity: usize) -> MaybeAtomicRefVec<T> {
+        MaybeAtomicRefVec {
+            arena: MaybeAtomicArena::with_capacity(capacity),
+        }
+    }
+
+    /// Consumes the `MaybeAtomicRefVec`, returning the wrapped `Vec`, without copying it.
+    #[inline]
+    pub fn into_inner(self) -> Vec<T> {
+        self.arena.into_vec()
+    }
+
+    /// Returns the number of elements.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.arena.len()
+    }
+
+    /// Returns `true` if there are no elements.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.arena.is_empty()
+    }
+
+    /// Appends `value`.
+    #[inline]
+    pub fn push(&mut self, value: T) {
+        self.arena.push(value);
+    }
+
+    /// Removes the last element and returns it, or `None` if there are none.
+    #[inline]
+    pub fn pop(&mut self) -> Option<T> {
+        #[cfg(any(debug_assertions, feature = "safe"))]
+        self.arena.flags.pop();
+        self.arena.values.pop().map(UnsafeCell::into_inner)
+    }
+
+    /// Shortens to the first `len` elements, dropping the rest.
+    #[inline]
+    pub fn truncate(&mut self, len: usize) {
+        #[cfg(any(debug_assertions, feature = "safe"))]
+        self.arena.flags.truncate(len);
+        self.arena.values.truncate(len);
+    }
+
+    /// Immutably borrows the element at `index`. Performs runtime checks in debug mode, but not
+    /// in release mode (hence `unsafe`).
+    ///
+    /// # Safety
+    ///
+    /// The element must not be mutably borrowed for the lifetime of the returned guard.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    #[inline]
+    #[track_caller]
+    pub unsafe fn borrow_index(&self, index: usize) -> MaybeAtomicRef<'_, T> {
+        unsafe { self.arena.borrow(index) }
+    }
+
+    /// Mutably borrows the element at `index`. Performs runtime checks in debug mode, but not in
+    /// release mode (hence `unsafe`).
+    ///
+    /// # Safety
+    ///
+    /// The element must not be borrowed at all for the lifetime of the returned guard.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    #[inline]
+    #[track_caller]
+    pub unsafe fn borrow_index_mut(&self, index: usize) -> MaybeAtomicRefMut<'_, T> {
+        unsafe { self.arena.borrow_mut(index) }
+    }
+
+    /// Returns a mutable reference to the element at `index`, if any.
+    ///
+    /// No runtime checks take place because this call borrows `MaybeAtomicRefVec` mutably at
+    /// compile-time.
+    #[inline]
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.arena.get_mut(index)
+    }
+
+    /// Returns a mutable slice of all of the elements.
+    ///
+    /// No runtime checks take place because this call borrows `MaybeAtomicRefVec` mutably at
+    /// compile-time.
+    #[inline]
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        let values = self.arena.values.as_mut_slice();
+        // SAFETY: `UnsafeCell<T>` has the same memory layout as `T`, and the unique borrow rules
+        // out other accesses.
+        unsafe { &mut *(values as *mut [UnsafeCell<T>] as *mut [T]) }
+    }
+
+    /// Removes all elements.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.arena.clear();
+    }
+}
+
+impl<T> Default for MaybeAtomicRefVec<T> {
+    #[inline]
+    fn default() -> MaybeAtomicRefVec<T> {
+        MaybeAtomicRefVec::new()
+    }
+}
+
+impl<T> FromIterator<T> for MaybeAtomicRefVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> MaybeAtomicRefVec<T> {
+        MaybeAtomicRefVec {
+            arena: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl<T> From<Vec<T>> for MaybeAtomicRefVec<T> {
+    /// Wraps `values` without copying them.
+    fn from(values: Vec<T>) -> MaybeAtomicRefVec<T> {
+        MaybeAtomicRefVec {
+            arena: values.into(),
+        }
+    }
+}
+
+impl<T: Debug> Debug for MaybeAtomicRefVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.arena.fmt(f)
+    }
+}
+
+/// Converts a `Vec<T>` to a `Vec<UnsafeCell<T>>` in place.
+#[inline]
+fn into_cells<T>(values: Vec<T>) -> Vec<UnsafeCell<T>> {
+    let mut values = std::mem::ManuallyDrop::new(values);
+    // SAFETY: `UnsafeCell<T>` has the same memory layout as `T`.
+    unsafe {
+        Vec::from_raw_parts(
+            values.as_mut_ptr() as *mut UnsafeCell<T>,
+            values.len(),
+            values.capacity(),
+        )
+    }
+}
+
+/// Converts a `Vec<UnsafeCell<T>>` to a `Vec<T>` in place.
+#[inline]
+fn from_cells<T>(values: Vec<UnsafeCell<T>>) -> Vec<T> {
+    let mut values = std::mem::ManuallyDrop::new(values);
+    // SAFETY: See `into_cells`.
+    unsafe {
+        Vec::from_raw_parts(
+            values.as_mut_ptr() as *mut T,
+            values.len(),
+            values.capacity(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::{MaybeAtomicArena, MaybeAtomicRefVec, MaybeAtomicSlab};
+
+    #[test]
+    fn it_works() {
+        let mut slab = MaybeAtomicSlab::new();
+        let a = slab.insert(1);
+        let b = slab.insert(2);
+        let c = slab.insert(3);
+        assert_eq!(slab.len(), 3);
+
+        unsafe {
+            let mut a = slab.get(a).unwrap().borrow_mut();
+            let mut c = slab.get(c).unwrap().borrow_mut();
+            *a += 10;
+            *c += 30;
+        }
+
+        assert_eq!(slab.remove(b), Some(2));
+        assert_eq!(slab.remove(b), None);
+        assert!(!slab.contains(b));
+        assert_eq!(slab.len(), 2);
+
+        // Keys are reused, but the remaining keys are stable.
+        let d = slab.insert(4);
+        assert_eq!(d, b);
+        *slab.get_mut(d).unwrap() += 40;
+        assert_eq!(
+            slab.iter()
+                .map(|(key, cell)| (key, unsafe { *cell.borrow() }))
+                .collect::<Vec<_>>(),
+            [(a, 11), (b, 44), (c, 33)]
+        );
+
+        slab.clear();
+        assert!(slab.is_empty());
+        assert_eq!(slab.insert(5), 0);
+    }
+
+    #[test]
+    fn vacant_list() {
+        let mut slab = MaybeAtomicSlab::with_capacity(4);
+        let keys: Vec<_> = (0..4).map(|i| slab.insert(i)).collect();
+        slab.remove(keys[1]);
+        slab.remove(keys[3]);
+
+        // Most recently vacated first.
+        assert_eq!(slab.insert(5), keys[3]);
+        assert_eq!(slab.insert(6), keys[1]);
+        assert_eq!(slab.insert(7), 4);
+        assert!(slab.get(5).is_none());
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(any(debug_assertions, feature = "safe"), should_panic)]
+    fn it_panics_mut_mut() {
+        let mut slab = MaybeAtomicSlab::new();
+        let key = slab.insert(5);
+        unsafe {
+            let _borrow1 = slab.get(key).unwrap().borrow_mut();
+            let _borrow2 = slab.get(key).unwrap().borrow_mut();
+        }
+    }
+
+    #[test]
+    fn arena() {
+        let mut arena: MaybeAtomicArena<_> = (0..3).collect();
+        assert_eq!(arena.push(3), 3);
+        assert_eq!(arena.len(), 4);
+
+        unsafe {
+            let mut a = arena.borrow_mut(0);
+            let mut b = arena.borrow_mut(1);
+            let c1 = arena.borrow(2);
+            let c2 = arena.borrow(2);
+            *a += *c1;
+            *b += *c2;
+        }
+        *arena.get_mut(3).unwrap() += 10;
+        assert_eq!(arena.into_vec(), [2, 3, 2, 13]);
+    }
+
+    #[test]
+    #[cfg(all(any(debug_assertions, feature = "safe"), not(feature = "abort")))]
+    fn arena_debug() {
+        let arena = MaybeAtomicArena::from(vec![1, 2]);
+        let _borrow = unsafe { arena.borrow_mut(1) };
+        assert_eq!(format!("{:?}", arena), "[1, <mutably borrowed>]");
+
+        // A failed borrow leaves the existing one in place.
+        let arena = std::panic::AssertUnwindSafe(&arena);
+        assert!(std::panic::catch_unwind(|| unsafe { arena.borrow_mut(1) }).is_err());
+        assert_eq!(format!("{:?}", *arena), "[1, <mutably borrowed>]");
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(
+        any(debug_assertions, feature = "safe"),
+        should_panic(expected = "already mutably borrowed by this thread (reentrant borrow)")
+    )]
+    fn it_panics_arena_mut_ref() {
+        let arena = MaybeAtomicArena::from(vec![1, 2]);
+        unsafe {
+            let _borrow1 = arena.borrow_mut(0);
+            let _borrow2 = arena.borrow(0);
+        }
+    }
+
+    #[test]
+    fn ref_vec() {
+        let buffer = vec![1, 2, 3, 4];
+        let ptr = buffer.as_ptr();
+        let mut vec = MaybeAtomicRefVec::from(buffer);
+
+        unsafe {
+            let mut a = vec.borrow_index_mut(0);
+            let mut b = vec.borrow_index_mut(3);
+            let c = vec.borrow_index(1);
+            *a += *c;
+            *b += *vec.borrow_index(1);
+        }
+        vec.as_mut_slice()[2] = 0;
+        assert_eq!(vec.pop(), Some(6));
+        vec.push(5);
+        vec.truncate(3);
+        assert_eq!(vec.len(), 3);
+
+        // The buffer is never copied.
+        let buffer = vec.into_inner();
+        assert_eq!((buffer.as_ptr(), buffer), (ptr, vec![3, 2, 0]));
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(any(debug_assertions, feature = "safe"), should_panic)]
+    fn it_panics_ref_vec_mut_mut() {
+        let vec = MaybeAtomicRefVec::from(vec![1, 2]);
+        unsafe {
+            let _borrow1 = vec.borrow_index_mut(1);
+            let _borrow2 = vec.borrow_index_mut(1);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(any(debug_assertions, feature = "safe"), should_panic)]
+    fn it_panics_arena_ref_mut() {
+        let arena = MaybeAtomicArena::from(vec![1, 2]);
+        unsafe {
+            let _borrow1 = arena.borrow(1);
+            let _borrow2 = arena.borrow_mut(1);
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! Mutably borrowing several cells of different types at once, like
+//! `MaybeAtomicRefCell::borrow_many_mut` does for cells of the same type.
+
+#[cfg(any(debug_assertions, feature = "safe"))]
+use crate::{fixed, reentrancy};
+use crate::{MaybeAtomicRefCell, MaybeAtomicRefMut};
+
+/// A tuple of references to distinct cells, which `borrow_all` mutably borrows at once.
+/// Implemented for tuples of up to 8 `&MaybeAtomicRefCell`s.
+pub trait BorrowAll<'b>: sealed::Sealed {
+    /// A tuple of a `MaybeAtomicRefMut` for each cell.
+    type Guards;
+
+    #[doc(hidden)]
+    unsafe fn borrow_all(self) -> Self::Guards;
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Mutably borrows a tuple of distinct cells, possibly of different types, at once, returning a
+/// tuple of guards. Performs runtime checks in debug mode, including that no cell is passed
+/// twice, but not in release mode (hence `unsafe`).
+///
+/// Like `MaybeAtomicRefCell::borrow_many_mut`, the cells are acquired in address order,
+/// regardless of the order they are passed in.
+///
+/// ```
+/// use maybe_atomic_refcell::{borrow_all, MaybeAtomicRefCell};
+///
+/// let positions = MaybeAtomicRefCell::new(vec![0.0, 1.0]);
+/// let velocity = MaybeAtomicRefCell::new(2.0);
+/// let moved = MaybeAtomicRefCell::new(0);
+///
+/// unsafe {
+///     let (mut positions, velocity, mut moved) = borrow_all((&positions, &velocity, &moved));
+///     for p in positions.iter_mut() {
+///         *p += *velocity;
+///         *moved += 1;
+///     }
+/// }
+/// assert_eq!(moved.into_inner(), 2);
+/// ```
+///
+/// # Safety
+///
+/// The cells must be distinct, and none of them may be borrowed at all for the lifetime of the
+/// returned guards.
+#[inline]
+#[track_caller]
+pub unsafe fn borrow_all<'b, C: BorrowAll<'b>>(cells: C) -> C::Guards {
+    unsafe { cells.borrow_all() }
+}
+
+/// Identifies a cell by its address and size, so that a cell at the start of another cell's
+/// value isn't mistaken for it.
+#[cfg(any(debug_assertions, feature = "safe"))]
+#[inline]
+fn key<T: ?Sized>(cell: &MaybeAtomicRefCell<T>) -> (usize, usize) {
+    (reentrancy::address(cell), std::mem::size_of_val(cell))
+}
+
+/// Panics if any cell is passed twice, and returns the order to acquire the cells in.
+#[cfg(any(debug_assertions, feature = "safe"))]
+#[inline]
+#[track_caller]
+fn order<const N: usize>(keys: [(usize, usize); N]) -> [usize; N] {
+    for (i, a) in keys.iter().enumerate() {
+        for (j, b) in keys[..i].iter().enumerate() {
+            if a == b {
+                fixed::violation(format_args!(
+                    "cells {} and {} are the same MaybeAtomicRefCell",
+                    j, i
+                ));
+            }
+        }
+    }
+    let mut order: [usize; N] = std::array::from_fn(|i| i);
+    order.sort_unstable_by_key(|&i| keys[i]);
+    order
+}
+
+/// Mutably borrows the `i`th cell, panicking if it is borrowed at all.
+#[cfg(any(debug_assertions, feature = "safe"))]
+#[inline]
+#[track_caller]
+fn acquire<T: ?Sized>(cell: &MaybeAtomicRefCell<T>, i: usize) -> MaybeAtomicRefMut<'_, T> {
+    let guard = fixed::acquire_mut(&cell.inner).unwrap_or_else(|| {
+        fixed::violation(format_args!(
+            "cell {} is already borrowed {}",
+            i,
+            reentrancy::culprit(reentrancy::id(&cell.inner))
+        ))
+    });
+    #[cfg(feature = "change_detection")]
+    cell.mark_changed();
+    guard
+}
+
+macro_rules! impl_borrow_all {
+    ($n:literal; $($i:tt $t:ident),+) => {
+        impl<'b, $($t: ?Sized),+> sealed::Sealed for ($(&'b MaybeAtomicRefCell<$t>,)+) {}
+
+        impl<'b, $($t: ?Sized),+> BorrowAll<'b> for ($(&'b MaybeAtomicRefCell<$t>,)+) {
+            type Guards = ($(MaybeAtomicRefMut<'b, $t>,)+);
+
+            #[inline]
+            #[track_caller]
+            unsafe fn borrow_all(self) -> Self::Guards {
+                #[cfg(any(debug_assertions, feature = "safe"))]
+                {
+                    let mut guards = ($(None::<MaybeAtomicRefMut<'b, $t>>,)+);
+                    for i in order::<$n>([$(key(self.$i)),+]) {
+                        match i {
+                            $($i => guards.$i = Some(acquire(self.$i, i)),)+
+                            _ => unreachable!(),
+                        }
+                    }
+                    ($(guards.$i.unwrap(),)+)
+                }
+                #[cfg(not(any(debug_assertions, feature = "safe")))]
+                unsafe {
+                    ($(self.$i.borrow_mut(),)+)
+                }
+            }
+        }
+    };
+}
+
+impl_borrow_all!(1; 0 A);
+impl_borrow_all!(2; 0 A, 1 B);
+impl_borrow_all!(3; 0 A, 1 B, 2 C);
+impl_borrow_all!(4; 0 A, 1 B, 2 C, 3 D);
+impl_borrow_all!(5; 0 A, 1 B, 2 C, 3 D, 4 E);
+impl_borrow_all!(6; 0 A, 1 B, 2 C, 3 D, 4 E, 5 F);
+impl_borrow_all!(7; 0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G);
+impl_borrow_all!(8; 0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G, 7 H);
+
+#[cfg(test)]
+mod tests {
+    use crate::{borrow_all, MaybeAtomicRefCell};
+
+    #[test]
+    fn it_works() {
+        let a = MaybeAtomicRefCell::new(1);
+        let b = MaybeAtomicRefCell::new(String::from("b"));
+        let c = MaybeAtomicRefCell::new(vec![3]);
+
+        unsafe {
+            let (mut a, mut b, c) = borrow_all((&a, &b, &c));
+            *a += c[0];
+            b.push('!');
+        }
+        assert_eq!(a.into_inner(), 4);
+        assert_eq!(b.into_inner(), "b!");
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(
+        any(debug_assertions, feature = "safe"),
+        should_panic(expected = "cells 0 and 2 are the same MaybeAtomicRefCell")
+    )]
+    fn it_panics_duplicate() {
+        let a = MaybeAtomicRefCell::new(1);
+        let b = MaybeAtomicRefCell::new(2.0);
+        unsafe {
+            let _borrows = borrow_all((&a, &b, &a));
+        }
+    }
+
+    #[test]
+    #[cfg_attr(feature = "abort", ignore = "aborts instead of panicking")]
+    #[cfg_attr(
+        any(debug_assertions, feature = "safe"),
+        should_panic(expected = "cell 1 is already borrowed by this thread")
+    )]
+    fn it_panics_borrowed() {
+        let a = MaybeAtomicRefCell::new(1);
+        let b = MaybeAtomicRefCell::new(2.0);
+        unsafe {
+            let _borrow = b.borrow();
+            let _borrows = borrow_all((&a, &b));
+        }
+    }
+}